@@ -0,0 +1,441 @@
+use crate::searchstats;
+use crate::{format_duration, i18n, refresh_listing, send_reply, BotControl, Context, Settings};
+use crate::{BotCommand, Lang, Outcome, Permission, User};
+use failure::Error;
+use irc::client::prelude::*;
+use log::info;
+
+// owner only commands
+const REFRESH_COMMAND: &str = "refresh";
+const RELOAD_COMMAND: &str = "reload";
+const STATS_COMMAND: &str = "stats";
+const IGNORE_COMMAND: &str = "ignore";
+const UNIGNORE_COMMAND: &str = "unignore";
+const QUIT_COMMAND: &str = "quit";
+const JOIN_COMMAND: &str = "join";
+const PART_COMMAND: &str = "part";
+const SAY_COMMAND: &str = "say";
+const FEATURE_COMMAND: &str = "feature";
+const UNFEATURE_COMMAND: &str = "unfeature";
+const SEARCHES_COMMAND: &str = "searches";
+/* period of the searches report without argument */
+const DEFAULT_SEARCHES_DAYS: u32 = 7;
+
+/// Logs an owner command, runs it and sends its reply or error in the owner
+/// language.
+fn owner_reply<F>(ctx: &Context, settings: &Settings, user: &User, args: &[&str], f: F) -> Outcome
+where
+    F: FnOnce(Lang) -> Result<String, Error>,
+{
+    info!("Admin command from {}: {}", user.hostmask, args.join(" "));
+    let lang = ctx.lang(settings, user);
+    let reply = match f(lang) {
+        Ok(reply) => reply,
+        Err(e) => i18n::error_message(lang, &e),
+    };
+    send_reply(ctx, settings, &user.nick, reply);
+    Outcome::default()
+}
+
+/// `refresh`: fetches the channel list again.
+pub struct RefreshCommand;
+
+impl BotCommand for RefreshCommand {
+    fn name(&self) -> &str {
+        REFRESH_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        REFRESH_COMMAND
+    }
+    fn summary(&self) -> &str {
+        "fetches the channel list from the server"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| {
+            refresh_listing(&ctx.client, &ctx.listing);
+            Ok(tr!(lang, "admin.refresh"))
+        })
+    }
+}
+
+/// `reload`: reads the configuration files again.
+pub struct ReloadCommand;
+
+impl BotCommand for ReloadCommand {
+    fn name(&self) -> &str {
+        RELOAD_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        RELOAD_COMMAND
+    }
+    fn summary(&self) -> &str {
+        "reloads the configuration files"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| {
+            ctx.network.control.send(BotControl::Reload)?;
+            Ok(tr!(lang, "admin.reload"))
+        })
+    }
+}
+
+/// `stats`: connection and cache counters.
+pub struct StatsCommand;
+
+impl BotCommand for StatsCommand {
+    fn name(&self) -> &str {
+        STATS_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        STATS_COMMAND
+    }
+    fn summary(&self) -> &str {
+        "shows connection uptime, cache and request counters"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| Ok(stats(ctx, lang)))
+    }
+}
+
+/// `ignore [mask]` and `unignore <mask>`.
+pub struct IgnoreCommand {
+    unignore: bool,
+}
+
+impl IgnoreCommand {
+    pub fn ignore() -> IgnoreCommand {
+        IgnoreCommand { unignore: false }
+    }
+    pub fn unignore() -> IgnoreCommand {
+        IgnoreCommand { unignore: true }
+    }
+}
+
+impl BotCommand for IgnoreCommand {
+    fn name(&self) -> &str {
+        if self.unignore {
+            UNIGNORE_COMMAND
+        } else {
+            IGNORE_COMMAND
+        }
+    }
+    fn syntax(&self) -> &str {
+        if self.unignore {
+            "unignore <mask>"
+        } else {
+            "ignore [mask]"
+        }
+    }
+    fn summary(&self) -> &str {
+        if self.unignore {
+            "stops ignoring a mask"
+        } else {
+            "ignores users matching a nick!user@host mask, or lists them"
+        }
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| {
+            if self.unignore {
+                unignore(ctx, &args[1..], lang)
+            } else {
+                ignore(ctx, &args[1..], lang)
+            }
+        })
+    }
+}
+
+fn ignore(ctx: &Context, args: &[&str], lang: Lang) -> Result<String, Error> {
+    let added = {
+        let mut ignores = ctx.network.ignores.write().unwrap();
+        match args.first() {
+            Some(mask) => ignores.add(mask),
+            None if ignores.masks().is_empty() => return Ok(tr!(lang, "admin.no_ignores")),
+            None => return Ok(tr!(lang, "admin.ignores", ignores.masks().join(" "))),
+        }
+    };
+    if added {
+        ctx.network.save_ignores()?;
+        Ok(tr!(lang, "admin.ignored", args[0]))
+    } else {
+        Ok(tr!(lang, "admin.already_ignored", args[0]))
+    }
+}
+
+fn unignore(ctx: &Context, args: &[&str], lang: Lang) -> Result<String, Error> {
+    let mask = match args.first() {
+        Some(mask) => mask,
+        None => return Err(localized!("admin.unignore_usage")),
+    };
+    let removed = ctx.network.ignores.write().unwrap().remove(mask);
+    if removed {
+        ctx.network.save_ignores()?;
+        Ok(tr!(lang, "admin.unignored", mask))
+    } else {
+        Ok(tr!(lang, "admin.not_ignored", mask))
+    }
+}
+
+/// `quit [message]`: stops the whole bot.
+pub struct QuitCommand;
+
+impl BotCommand for QuitCommand {
+    fn name(&self) -> &str {
+        QUIT_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "quit [message]"
+    }
+    fn summary(&self) -> &str {
+        "quits every network and stops the bot"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| {
+            let message = if args.len() > 1 {
+                Some(args[1..].join(" "))
+            } else {
+                None
+            };
+            ctx.network.control.send(BotControl::Shutdown(message))?;
+            Ok(tr!(lang, "admin.quit"))
+        })
+    }
+}
+
+/// `join <#channel> [key]`.
+pub struct JoinCommand;
+
+impl BotCommand for JoinCommand {
+    fn name(&self) -> &str {
+        JOIN_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "join <#channel> [key]"
+    }
+    fn summary(&self) -> &str {
+        "joins a channel"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| match &args[1..] {
+            [channel] => {
+                ctx.client.send_join(channel)?;
+                Ok(tr!(lang, "admin.join", channel))
+            }
+            [channel, key] => {
+                ctx.client.send_join_with_keys::<&str, &str>(channel, key)?;
+                Ok(tr!(lang, "admin.join", channel))
+            }
+            _ => Err(localized!("admin.join_usage")),
+        })
+    }
+}
+
+/// `part <#channel> [message]`.
+pub struct PartCommand;
+
+impl BotCommand for PartCommand {
+    fn name(&self) -> &str {
+        PART_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "part <#channel> [message]"
+    }
+    fn summary(&self) -> &str {
+        "leaves a channel"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| match &args[1..] {
+            [channel, message @ ..] => {
+                let message = if message.is_empty() {
+                    None
+                } else {
+                    Some(message.join(" "))
+                };
+                ctx.client
+                    .send(Command::PART(channel.to_string(), message))?;
+                Ok(tr!(lang, "admin.part", channel))
+            }
+            _ => Err(localized!("admin.part_usage")),
+        })
+    }
+}
+
+/// `say <target> <text>`.
+pub struct SayCommand;
+
+impl BotCommand for SayCommand {
+    fn name(&self) -> &str {
+        SAY_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "say <target> <text>"
+    }
+    fn summary(&self) -> &str {
+        "sends a message"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| match &args[1..] {
+            [target, text @ ..] if !text.is_empty() => {
+                ctx.client.send_privmsg(target, text.join(" "))?;
+                Ok(tr!(lang, "admin.say", target))
+            }
+            _ => Err(localized!("admin.say_usage")),
+        })
+    }
+}
+
+/// `feature <#channel> <blurb>` and `unfeature <#channel>`.
+pub struct FeatureCommand {
+    unfeature: bool,
+}
+
+impl FeatureCommand {
+    pub fn feature() -> FeatureCommand {
+        FeatureCommand { unfeature: false }
+    }
+    pub fn unfeature() -> FeatureCommand {
+        FeatureCommand { unfeature: true }
+    }
+}
+
+impl BotCommand for FeatureCommand {
+    fn name(&self) -> &str {
+        if self.unfeature {
+            UNFEATURE_COMMAND
+        } else {
+            FEATURE_COMMAND
+        }
+    }
+    fn syntax(&self) -> &str {
+        if self.unfeature {
+            "unfeature <#channel>"
+        } else {
+            "feature <#channel> <blurb>"
+        }
+    }
+    fn summary(&self) -> &str {
+        if self.unfeature {
+            "stops promoting a channel"
+        } else {
+            "promotes a channel"
+        }
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| {
+            if self.unfeature {
+                unfeature(ctx, &args[1..], lang)
+            } else {
+                feature(ctx, &args[1..], lang)
+            }
+        })
+    }
+}
+
+fn feature(ctx: &Context, args: &[&str], lang: Lang) -> Result<String, Error> {
+    match args {
+        [channel, blurb @ ..] if channel.starts_with('#') && !blurb.is_empty() => {
+            ctx.network
+                .featured
+                .write()
+                .unwrap()
+                .add(channel, &blurb.join(" "))?;
+            ctx.network.save_featured()?;
+            Ok(tr!(lang, "admin.featured", channel))
+        }
+        _ => Err(localized!("admin.feature_usage")),
+    }
+}
+
+fn unfeature(ctx: &Context, args: &[&str], lang: Lang) -> Result<String, Error> {
+    match args {
+        [channel] => {
+            let removed = ctx.network.featured.write().unwrap().remove(channel);
+            if removed {
+                ctx.network.save_featured()?;
+                Ok(tr!(lang, "admin.unfeatured", channel))
+            } else {
+                Ok(tr!(lang, "admin.not_featured", channel))
+            }
+        }
+        _ => Err(localized!("admin.unfeature_usage")),
+    }
+}
+
+/// `searches [period]`: most common searches of the network.
+pub struct SearchesCommand;
+
+impl BotCommand for SearchesCommand {
+    fn name(&self) -> &str {
+        SEARCHES_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "searches [period]"
+    }
+    fn summary(&self) -> &str {
+        "most common searches, e.g. searches 2w"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        owner_reply(ctx, settings, user, args, |lang| {
+            let days = match args.get(1) {
+                Some(period) => searchstats::parse_period(period)?,
+                None => DEFAULT_SEARCHES_DAYS,
+            };
+            /* older counters are not kept */
+            let days = days.min(settings.search_stats_days);
+            let summary = ctx
+                .network
+                .searches
+                .lock()
+                .unwrap()
+                .summary(chrono::Utc::now().date().naive_utc(), days);
+            Ok(searchstats::report(&summary, days, true, lang).join("\r\n"))
+        })
+    }
+}
+
+fn stats(ctx: &Context, lang: Lang) -> String {
+    let (channels, last_fetch) = {
+        let (mtx, _cnd) = &*ctx.listing;
+        let guard = mtx.lock().unwrap();
+        (guard.1.len(), guard.1.get_elapsed_time())
+    };
+    let stats = ctx.stats.lock().unwrap();
+    tr!(
+        lang,
+        "admin.stats",
+        format_duration(stats.connected.elapsed()),
+        channels,
+        format_duration(last_fetch),
+        stats.requests,
+        ctx.network.ignores.read().unwrap().masks().len()
+    )
+}
@@ -0,0 +1,176 @@
+use crate::Request;
+use chrono::{DateTime, Utc};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A handled command, written as one JSON line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub network: String,
+    pub nick: String,
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    pub command: String,
+    /// parsed `list` request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<RequestRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<usize>,
+    /// processing time, replies excluded
+    pub duration_ms: u64,
+    pub forced_refresh: bool,
+}
+
+/// Search criteria of a `list` request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestRecord {
+    pub pattern: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_users: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_users: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl From<&Request> for RequestRecord {
+    fn from(request: &Request) -> RequestRecord {
+        RequestRecord {
+            pattern: request.chan_pattern.to_string(),
+            topic: request.topic_pattern.as_ref().map(|p| p.to_string()),
+            min_users: request.min_users,
+            max_users: request.max_users,
+            limit: request.limit,
+            tags: request.tags.clone(),
+            description: request.desc_pattern.as_ref().map(|p| p.to_string()),
+        }
+    }
+}
+
+/// Append only JSON-lines file, rotated to `<path>.1`, `<path>.2`... once it
+/// exceeds `max_size` bytes.
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    /// rotated files kept
+    files: usize,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path, max_size: u64, files: usize) -> Result<AuditLog, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format_err!("{}: {}", path.display(), e))?;
+        let size = file.metadata()?.len();
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            files,
+        })
+    }
+    /// Whether the log was opened with these settings.
+    pub fn is_same(&self, path: &Path, max_size: u64, files: usize) -> bool {
+        self.path == path && self.max_size == max_size && self.files == files
+    }
+    pub fn write(&mut self, record: &AuditRecord) -> Result<(), Error> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+    fn rotate(&mut self) -> Result<(), Error> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.files).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        *self = AuditLog::open(&self.path, self.max_size, self.files)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(nick: &str) -> AuditRecord {
+        AuditRecord {
+            timestamp: Utc::now(),
+            network: "libera".to_string(),
+            nick: nick.to_string(),
+            host: "host".to_string(),
+            account: None,
+            command: "list".to_string(),
+            request: Some(RequestRecord {
+                pattern: "*rust*".to_string(),
+                topic: None,
+                min_users: Some(10),
+                max_users: None,
+                limit: None,
+                tags: Vec::new(),
+                description: None,
+            }),
+            results: Some(3),
+            duration_ms: 2,
+            forced_refresh: false,
+        }
+    }
+    #[test]
+    fn json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let mut log = AuditLog::open(&path, 1 << 20, 2).unwrap();
+        log.write(&record("nick1")).unwrap();
+        log.write(&record("nick2")).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"pattern\":\"*rust*\""));
+        assert!(!lines[0].contains("account"));
+        let parsed: AuditRecord = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(parsed.nick, "nick2");
+        assert_eq!(parsed.results, Some(3));
+    }
+    #[test]
+    fn rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let line_len = serde_json::to_string(&record("nick0")).unwrap().len() as u64 + 1;
+        let mut log = AuditLog::open(&path, line_len * 2, 2).unwrap();
+        for i in 0..7 {
+            log.write(&record(&format!("nick{}", i))).unwrap();
+        }
+        let read = |p: &str| fs::read_to_string(dir.path().join(p)).unwrap();
+        assert!(read("audit.log").contains("nick6"));
+        assert!(read("audit.log.1").contains("nick5"));
+        assert!(read("audit.log.2").contains("nick3"));
+        assert!(!dir.path().join("audit.log.3").exists());
+    }
+}
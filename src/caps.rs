@@ -0,0 +1,422 @@
+use crate::settings::Sasl;
+use irc::client::prelude::*;
+use irc::proto::message::Tag;
+use irc::proto::CapSubCommand;
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+/// capability tagging messages with the services account of their sender
+pub const ACCOUNT_TAG_CAP: &str = "account-tag";
+/// IRCv3 capabilities requested when the server offers them.
+pub const WANTED_CAPS: &[&str] = &[
+    "message-tags",
+    "server-time",
+    ACCOUNT_TAG_CAP,
+    "batch",
+    "labeled-response",
+    "echo-message",
+    "draft/multiline",
+];
+/* tag the server adds to messages of clients in bot mode */
+pub const BOT_TAG: &str = "bot";
+const SASL_CAP: &str = "sasl";
+/* AUTHENTICATE payloads are sent in chunks of this size */
+const SASL_CHUNK_BYTES: usize = 400;
+// not known by irc-proto, received as a raw command
+const ERR_NICKLOCKED: &str = "902";
+
+#[derive(Debug, Default)]
+struct State {
+    /// capabilities offered by the server, with their values
+    offered: BTreeMap<String, Option<String>>,
+    enabled: BTreeSet<String>,
+    /// capabilities of the `CAP REQ` not answered yet
+    pending: Vec<Vec<String>>,
+    sasl: Option<Sasl>,
+    /// SASL login started and not finished yet
+    authenticating: bool,
+    /// `CAP END` was sent, the registration went on
+    ended: bool,
+}
+
+/// Capability negotiation started by `CAP LS 302` before the registration,
+/// which the server holds until `CAP END`, and SASL login.
+#[derive(Debug, Default)]
+pub struct Caps {
+    state: Mutex<State>,
+}
+
+impl Caps {
+    pub fn new(sasl: Option<Sasl>) -> Caps {
+        Caps {
+            state: Mutex::new(State {
+                sasl,
+                ..State::default()
+            }),
+        }
+    }
+    /// Whether the server enabled the capability `cap`.
+    pub fn enabled(&self, cap: &str) -> bool {
+        self.state.lock().unwrap().enabled.contains(cap)
+    }
+    /// Value of an enabled capability, e.g. `max-bytes=4096` for `draft/multiline`.
+    pub fn value(&self, cap: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        if !state.enabled.contains(cap) {
+            return None;
+        }
+        state.offered.get(cap).cloned().flatten()
+    }
+    /// Enabled capabilities, for logs and statistics.
+    pub fn list(&self) -> Vec<String> {
+        self.state.lock().unwrap().enabled.iter().cloned().collect()
+    }
+    /// Handles `CAP` and SASL replies of the server, returning the commands to
+    /// send back.
+    pub fn handle(&self, message: &Message) -> Vec<Command> {
+        let mut state = self.state.lock().unwrap();
+        match &message.command {
+            /* "CAP * LS * :caps" announces more lines of capabilities */
+            Command::CAP(_, subcommand, Some(star), Some(list)) => {
+                state.cap(subcommand, list, star == "*")
+            }
+            Command::CAP(_, subcommand, Some(list), None) => state.cap(subcommand, list, false),
+            Command::AUTHENTICATE(challenge) if challenge == "+" => state.credentials(),
+            Command::Response(Response::RPL_SASLSUCCESS, _) => {
+                info!("Logged in with SASL");
+                state.authenticated()
+            }
+            Command::Response(
+                Response::ERR_SASLFAIL
+                | Response::ERR_SASLTOOLONG
+                | Response::ERR_SASLABORT
+                | Response::ERR_SASLALREADY,
+                args,
+            ) => {
+                warn!(
+                    "SASL login failed: {}",
+                    args.last().map_or("", |a| a.as_str())
+                );
+                state.authenticated()
+            }
+            Command::Raw(code, args) if code == ERR_NICKLOCKED => {
+                warn!(
+                    "SASL login failed: {}",
+                    args.last().map_or("", |a| a.as_str())
+                );
+                state.authenticated()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl State {
+    fn cap(&mut self, subcommand: &CapSubCommand, list: &str, more: bool) -> Vec<Command> {
+        let caps: Vec<String> = list.split_whitespace().map(|c| c.to_string()).collect();
+        match subcommand {
+            CapSubCommand::LS | CapSubCommand::NEW => {
+                for cap in &caps {
+                    let (name, value) = match cap.find('=') {
+                        Some(i) => (&cap[..i], Some(cap[i + 1..].to_string())),
+                        None => (cap.as_str(), None),
+                    };
+                    self.offered.insert(name.to_string(), value);
+                }
+                if more {
+                    return Vec::new();
+                }
+                let mut wanted: Vec<String> = WANTED_CAPS
+                    .iter()
+                    .filter(|cap| self.offered.contains_key(**cap))
+                    .filter(|cap| !self.enabled.contains(**cap))
+                    .filter(|cap| !self.pending.iter().flatten().any(|p| p == *cap))
+                    .map(|cap| cap.to_string())
+                    .collect();
+                /* logging in is only possible before the registration ends */
+                if let (Some(sasl), false) = (&self.sasl, self.ended) {
+                    let mechanism = sasl.mechanism();
+                    match self.offered.get(SASL_CAP) {
+                        /* the value lists the mechanisms, when given */
+                        Some(Some(mechanisms))
+                            if !mechanisms
+                                .split(',')
+                                .any(|m| m.eq_ignore_ascii_case(mechanism)) =>
+                        {
+                            warn!("SASL {} not supported by the server", mechanism)
+                        }
+                        Some(_) => wanted.push(SASL_CAP.to_string()),
+                        None => warn!("SASL not supported by the server"),
+                    }
+                }
+                if wanted.is_empty() {
+                    return self.end();
+                }
+                let req = request(&wanted);
+                self.pending.push(wanted);
+                vec![req]
+            }
+            CapSubCommand::ACK => {
+                self.answered(&caps);
+                let mut commands = Vec::new();
+                for cap in caps {
+                    match cap.strip_prefix('-') {
+                        Some(disabled) => {
+                            self.enabled.remove(disabled);
+                        }
+                        None => {
+                            if let (SASL_CAP, Some(sasl)) = (cap.as_str(), &self.sasl) {
+                                self.authenticating = true;
+                                commands.push(Command::AUTHENTICATE(sasl.mechanism().to_string()));
+                            }
+                            self.enabled.insert(cap);
+                        }
+                    }
+                }
+                commands.extend(self.end());
+                commands
+            }
+            CapSubCommand::NAK => {
+                /* a request is refused as a whole, ask again one by one */
+                let mut commands = Vec::new();
+                if caps.len() > 1 {
+                    for cap in &caps {
+                        let single = vec![cap.clone()];
+                        commands.push(request(&single));
+                        self.pending.push(single);
+                    }
+                }
+                self.answered(&caps);
+                commands.extend(self.end());
+                commands
+            }
+            CapSubCommand::DEL => {
+                for cap in &caps {
+                    self.offered.remove(cap);
+                    self.enabled.remove(cap);
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+    /// Forgets the request matching an ACK or NAK of `caps`.
+    fn answered(&mut self, caps: &[String]) {
+        let answered: BTreeSet<&str> = caps.iter().map(|cap| cap.trim_start_matches('-')).collect();
+        /* servers may reorder the capabilities, or else the oldest request is answered */
+        let i = self
+            .pending
+            .iter()
+            .position(|request| {
+                request
+                    .iter()
+                    .map(|cap| cap.as_str())
+                    .collect::<BTreeSet<_>>()
+                    == answered
+            })
+            .unwrap_or(0);
+        if i < self.pending.len() {
+            self.pending.remove(i);
+        }
+    }
+    /// Answers the server invitation to send the SASL credentials.
+    fn credentials(&self) -> Vec<Command> {
+        let payload = match (&self.sasl, self.authenticating) {
+            (Some(Sasl::Plain { username, password }), true) => {
+                base64(format!("{}\0{}\0{}", username, username, password).as_bytes())
+            }
+            /* the server checks the certificate of the connection */
+            (Some(Sasl::External), true) => String::new(),
+            _ => return Vec::new(),
+        };
+        let mut commands: Vec<Command> = payload
+            .as_bytes()
+            .chunks(SASL_CHUNK_BYTES)
+            .map(|chunk| Command::AUTHENTICATE(String::from_utf8_lossy(chunk).to_string()))
+            .collect();
+        /* a payload ending on a full chunk, or empty, is terminated by "+" */
+        if payload.len() % SASL_CHUNK_BYTES == 0 {
+            commands.push(Command::AUTHENTICATE("+".to_string()));
+        }
+        commands
+    }
+    /// Goes on with the registration once the SASL login succeeded or failed.
+    fn authenticated(&mut self) -> Vec<Command> {
+        if !self.authenticating {
+            return Vec::new();
+        }
+        self.authenticating = false;
+        self.end()
+    }
+    /// Ends the negotiation once every request is answered and the SASL login
+    /// is over.
+    fn end(&mut self) -> Vec<Command> {
+        if self.ended || self.authenticating || !self.pending.is_empty() {
+            return Vec::new();
+        }
+        self.ended = true;
+        vec![Command::CAP(None, CapSubCommand::END, None, None)]
+    }
+}
+
+fn request(caps: &[String]) -> Command {
+    Command::CAP(None, CapSubCommand::REQ, None, Some(caps.join(" ")))
+}
+
+/// Standard base64 encoding, with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Mode marking the bot as such, from the `BOT=` token of RPL_ISUPPORT.
+pub fn bot_mode(isupport: &[String]) -> Option<char> {
+    isupport
+        .iter()
+        .find_map(|token| token.strip_prefix("BOT="))
+        .and_then(|mode| mode.chars().next())
+}
+
+/// Whether the message carries the tag `key`.
+pub fn has_tag(message: &Message, key: &str) -> bool {
+    message.tags.iter().flatten().any(|Tag(k, _)| k == key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(caps: &Caps, line: &str) -> Vec<String> {
+        let message = format!("{}\r\n", line).parse::<Message>().unwrap();
+        caps.handle(&message)
+            .into_iter()
+            .map(|command| Message::from(command).to_string().trim_end().to_string())
+            .collect()
+    }
+    #[test]
+    fn negotiation() {
+        let caps = Caps::new(None);
+        assert!(reply(&caps, ":server CAP * LS * :sasl=PLAIN batch server-time").is_empty());
+        assert_eq!(
+            reply(&caps, ":server CAP * LS :account-tag"),
+            vec!["CAP REQ :server-time account-tag batch"]
+        );
+        assert_eq!(
+            reply(&caps, ":server CAP * ACK :server-time account-tag batch"),
+            vec!["CAP END"]
+        );
+        assert!(caps.enabled("account-tag"));
+        assert_eq!(caps.value("draft/multiline"), None);
+        assert!(!caps.enabled("sasl"));
+        /* capabilities offered later */
+        assert_eq!(
+            reply(&caps, ":server CAP alis NEW :echo-message"),
+            vec!["CAP REQ echo-message"]
+        );
+        assert!(reply(&caps, ":server CAP alis ACK :echo-message").is_empty());
+        reply(
+            &caps,
+            ":server CAP alis NEW :draft/multiline=max-bytes=4096",
+        );
+        reply(&caps, ":server CAP alis ACK :draft/multiline");
+        assert_eq!(
+            caps.value("draft/multiline"),
+            Some("max-bytes=4096".to_string())
+        );
+        assert!(caps.enabled("echo-message"));
+        assert!(reply(&caps, ":server CAP alis DEL :echo-message").is_empty());
+        assert!(!caps.enabled("echo-message"));
+    }
+    #[test]
+    fn refused_requests() {
+        let caps = Caps::new(None);
+        assert_eq!(reply(&caps, ":server CAP * LS :sasl"), vec!["CAP END"]);
+
+        let caps = Caps::new(None);
+        reply(&caps, ":server CAP * LS :batch echo-message");
+        assert_eq!(
+            reply(&caps, ":server CAP * NAK :batch echo-message"),
+            vec!["CAP REQ batch", "CAP REQ echo-message"]
+        );
+        assert!(reply(&caps, ":server CAP * ACK batch").is_empty());
+        assert_eq!(
+            reply(&caps, ":server CAP * NAK echo-message"),
+            vec!["CAP END"]
+        );
+        assert_eq!(caps.list(), vec!["batch"]);
+    }
+    #[test]
+    fn sasl_login() {
+        let plain = Sasl::Plain {
+            username: "alis".to_string(),
+            password: "secret".to_string(),
+        };
+        let caps = Caps::new(Some(plain));
+        assert_eq!(
+            reply(&caps, ":server CAP * LS :sasl=PLAIN,EXTERNAL batch"),
+            vec!["CAP REQ :batch sasl"]
+        );
+        /* registration held until the login is over */
+        assert_eq!(
+            reply(&caps, ":server CAP * ACK :batch sasl"),
+            vec!["AUTHENTICATE PLAIN"]
+        );
+        assert_eq!(
+            reply(&caps, "AUTHENTICATE +"),
+            vec!["AUTHENTICATE YWxpcwBhbGlzAHNlY3JldA=="]
+        );
+        assert_eq!(
+            reply(&caps, ":server 903 alis :SASL authentication successful"),
+            vec!["CAP END"]
+        );
+
+        let caps = Caps::new(Some(Sasl::External));
+        reply(&caps, ":server CAP * LS :sasl");
+        reply(&caps, ":server CAP * ACK :sasl");
+        assert_eq!(reply(&caps, "AUTHENTICATE +"), vec!["AUTHENTICATE +"]);
+        assert_eq!(
+            reply(&caps, ":server 904 alis :SASL authentication failed"),
+            vec!["CAP END"]
+        );
+        /* unsupported mechanism */
+        let caps = Caps::new(Some(Sasl::External));
+        assert_eq!(
+            reply(&caps, ":server CAP * LS :sasl=PLAIN"),
+            vec!["CAP END"]
+        );
+    }
+    #[test]
+    fn base64_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+    #[test]
+    fn bot_tags() {
+        let isupport: Vec<String> = vec!["CHANTYPES=#".into(), "BOT=B".into()];
+        assert_eq!(bot_mode(&isupport), Some('B'));
+        assert_eq!(bot_mode(&isupport[..1]), None);
+        let message = "@bot;account=x :b!b@h PRIVMSG alis :help\r\n"
+            .parse::<Message>()
+            .unwrap();
+        assert!(has_tag(&message, BOT_TAG));
+        assert!(!has_tag(&message, "label"));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::audit::RequestRecord;
+use crate::{admin, directory, featured, help, optout, prefs, suspicious};
+use crate::{Context, Lang, ListCommand, PopularCommand, Settings, User};
+use failure::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Users allowed to run a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Everyone,
+    /// operators of the channel given to the command, and owners. The command
+    /// checks it with `Context::is_channel_op` as only it knows the channel.
+    ChannelOperator,
+    /// services accounts listed in `owners`, checked before the command runs
+    Owner,
+}
+
+/// What a command did, written to the audit log.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Outcome {
+    pub request: Option<RequestRecord>,
+    pub results: Option<usize>,
+    /// processing time, the whole command duration when not set
+    pub duration: Option<Duration>,
+    pub forced_refresh: bool,
+}
+
+/// A command users send to the bot over private messages.
+///
+/// Commands of a network run one at a time on its command thread, so they
+/// may block, e.g. to wait for a WHOIS or the channel list. `run` is synchronous
+/// for that reason: the waits use condition variables filled by the connection
+/// task, and keeping commands off the async runtime means a slow one never
+/// stalls the IRC stream.
+pub trait BotCommand: Send + Sync {
+    /// Lowercase name the command is invoked with.
+    fn name(&self) -> &str;
+    /// Other lowercase names of the command.
+    fn aliases(&self) -> &[&str] {
+        &[]
+    }
+    /// Usage line, e.g. `optout <#channel>`.
+    fn syntax(&self) -> &str;
+    /// One line description shown by `help`, in English. Translations are
+    /// looked up in the catalogs with the `cmd.<name>` key.
+    fn summary(&self) -> &str;
+    /// Detailed usage shown by `help <command>`, `{nick}` being replaced by the
+    /// bot nickname. The `cmd.<name>.help` catalog message is used instead when
+    /// translated.
+    fn help(&self, _lang: Lang) -> Vec<String> {
+        Vec::new()
+    }
+    fn permission(&self) -> Permission {
+        Permission::Everyone
+    }
+    /// Disabled commands are unknown to users.
+    fn is_enabled(&self, _settings: &Settings) -> bool {
+        true
+    }
+    /// Runs the command, `args` being the whole command line with its original case.
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome;
+}
+
+/// Commands known to a network, looked up by name or alias.
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    commands: Vec<Arc<dyn BotCommand>>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> CommandRegistry {
+        CommandRegistry {
+            commands: Vec::new(),
+        }
+    }
+    /// Registry with the commands of alis-bot-rs.
+    pub fn with_builtins() -> CommandRegistry {
+        let mut registry = CommandRegistry::new();
+        let builtins: Vec<Arc<dyn BotCommand>> = vec![
+            Arc::new(ListCommand),
+            Arc::new(featured::FeaturedCommand),
+            Arc::new(PopularCommand),
+            Arc::new(optout::OptOutCommand::optout()),
+            Arc::new(optout::OptOutCommand::optin()),
+            Arc::new(directory::DirectoryCommand::register()),
+            Arc::new(directory::DirectoryCommand::tag()),
+            Arc::new(prefs::SetCommand),
+            Arc::new(prefs::SettingsCommand),
+            Arc::new(help::HelpCommand),
+            /* owner commands */
+            Arc::new(admin::RefreshCommand),
+            Arc::new(admin::ReloadCommand),
+            Arc::new(admin::StatsCommand),
+            Arc::new(admin::IgnoreCommand::ignore()),
+            Arc::new(admin::IgnoreCommand::unignore()),
+            Arc::new(admin::QuitCommand),
+            Arc::new(admin::JoinCommand),
+            Arc::new(admin::PartCommand),
+            Arc::new(admin::SayCommand),
+            Arc::new(admin::FeatureCommand::feature()),
+            Arc::new(admin::FeatureCommand::unfeature()),
+            Arc::new(suspicious::SuspiciousCommand),
+            Arc::new(admin::SearchesCommand),
+        ];
+        for command in builtins {
+            registry.insert(command).unwrap();
+        }
+        registry
+    }
+    /// Adds a command, failing when its name or an alias is already taken.
+    pub fn register<C: BotCommand + 'static>(&mut self, command: C) -> Result<(), Error> {
+        self.insert(Arc::new(command))
+    }
+    /// Replaces the command with the same name, or adds it, failing when an
+    /// alias is taken by another command.
+    pub fn replace<C: BotCommand + 'static>(&mut self, command: C) -> Result<(), Error> {
+        let command: Arc<dyn BotCommand> = Arc::new(command);
+        let mut replaced = self.clone();
+        replaced.commands.retain(|c| c.name() != command.name());
+        replaced.insert(command)?;
+        *self = replaced;
+        Ok(())
+    }
+    fn insert(&mut self, command: Arc<dyn BotCommand>) -> Result<(), Error> {
+        let names = std::iter::once(command.name()).chain(command.aliases().iter().cloned());
+        for name in names {
+            if self.find(name).is_some() {
+                return Err(format_err!("Command {} is already registered", name));
+            }
+        }
+        self.commands.push(command);
+        Ok(())
+    }
+    pub fn find(&self, name: &str) -> Option<&dyn BotCommand> {
+        self.commands
+            .iter()
+            .find(|c| c.name() == name || c.aliases().contains(&name))
+            .map(|c| c.as_ref())
+    }
+    /// Commands in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn BotCommand> {
+        self.commands.iter().map(|c| c.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ping;
+    impl BotCommand for Ping {
+        fn name(&self) -> &str {
+            "ping"
+        }
+        fn aliases(&self) -> &[&str] {
+            &["p"]
+        }
+        fn syntax(&self) -> &str {
+            "ping"
+        }
+        fn summary(&self) -> &str {
+            "answers pong"
+        }
+        fn run(&self, _: &Context, _: &Settings, _: &User, _: &[&str]) -> Outcome {
+            Outcome::default()
+        }
+    }
+
+    /* takes the alias of `Ping` */
+    struct Pong;
+    impl BotCommand for Pong {
+        fn name(&self) -> &str {
+            "pong"
+        }
+        fn aliases(&self) -> &[&str] {
+            &["p"]
+        }
+        fn syntax(&self) -> &str {
+            "pong"
+        }
+        fn summary(&self) -> &str {
+            "answers ping"
+        }
+        fn run(&self, _: &Context, _: &Settings, _: &User, _: &[&str]) -> Outcome {
+            Outcome::default()
+        }
+    }
+
+    #[test]
+    fn builtin_commands() {
+        let registry = CommandRegistry::with_builtins();
+        assert_eq!(registry.find("list").unwrap().name(), "list");
+        assert_eq!(
+            registry.find("refresh").unwrap().permission(),
+            Permission::Owner
+        );
+        assert_eq!(
+            registry.find("optin").unwrap().permission(),
+            Permission::ChannelOperator
+        );
+        assert_eq!(
+            registry.find("unignore").unwrap().syntax(),
+            "unignore <mask>"
+        );
+        assert_eq!(
+            registry.find("suspicious").unwrap().permission(),
+            Permission::Owner
+        );
+        assert!(registry.find("LIST").is_none());
+        assert!(registry.find("unknown").is_none());
+        let popular = registry.find("popular").unwrap();
+        assert!(!popular.is_enabled(&Settings::default()));
+    }
+    #[test]
+    fn catalog_summaries() {
+        for command in CommandRegistry::with_builtins().iter() {
+            let key = format!("cmd.{}", command.name());
+            assert_eq!(
+                crate::i18n::lookup(Lang::En, &key),
+                Some(command.summary()),
+                "{}",
+                key
+            );
+        }
+    }
+    #[test]
+    fn register_commands() {
+        let mut registry = CommandRegistry::with_builtins();
+        let count = registry.iter().count();
+        registry.register(Ping).unwrap();
+        assert_eq!(registry.find("p").unwrap().name(), "ping");
+        assert!(registry.register(Ping).is_err());
+        assert_eq!(registry.iter().count(), count + 1);
+        registry.replace(Ping).unwrap();
+        assert_eq!(registry.iter().count(), count + 1);
+        assert_eq!(registry.iter().last().unwrap().name(), "ping");
+        assert!(registry.replace(Pong).is_err());
+        assert!(registry.find("pong").is_none());
+    }
+}
@@ -0,0 +1,320 @@
+use crate::i18n::{self, Lang};
+use crate::listfile;
+use crate::{send_reply, BotCommand, Context, Outcome, Permission, Settings, User};
+use failure::Error;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+pub const REGISTER_COMMAND: &str = "register";
+pub const TAG_COMMAND: &str = "tag";
+const MAX_DESCRIPTION_LEN: usize = 300;
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LEN: usize = 20;
+
+/// Details supplied by the operators of a channel.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+}
+
+impl ChannelInfo {
+    fn is_empty(&self) -> bool {
+        *self == ChannelInfo::default()
+    }
+    /// Reply line describing the channel entry.
+    pub fn show(&self, lang: Lang, channel: &str) -> String {
+        let none = || tr!(lang, "register.none_value");
+        let tags: Vec<&str> = self.tags.iter().map(|t| t.as_str()).collect();
+        tr!(
+            lang,
+            "register.show",
+            channel,
+            self.description.clone().unwrap_or_else(none),
+            self.language.clone().unwrap_or_else(none),
+            self.website.clone().unwrap_or_else(none),
+            if tags.is_empty() {
+                none()
+            } else {
+                tags.join(" ")
+            }
+        )
+    }
+}
+
+/// Registered channels, by lowercase name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Directory {
+    channels: BTreeMap<String, ChannelInfo>,
+}
+
+impl Directory {
+    pub fn new() -> Directory {
+        Directory {
+            channels: BTreeMap::new(),
+        }
+    }
+    /// Reads a TOML file with one table per channel. A missing file is an empty
+    /// directory.
+    pub fn load(path: &Path) -> Result<Directory, Error> {
+        let content = match listfile::read(path)? {
+            Some(content) => content,
+            None => return Ok(Directory::new()),
+        };
+        let channels =
+            toml::from_str(&content).map_err(|e| format_err!("{}: {}", path.display(), e))?;
+        Ok(Directory { channels })
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = toml::to_string(&self.channels)?;
+        listfile::write_atomic(path, &content)
+    }
+    pub fn get(&self, channel: &str) -> Option<&ChannelInfo> {
+        self.channels.get(&channel.to_lowercase())
+    }
+    /// Changes the entry of a channel, dropping it once empty.
+    fn update<F: FnOnce(&mut ChannelInfo)>(&mut self, channel: &str, f: F) {
+        let key = channel.to_lowercase();
+        let info = self.channels.entry(key.clone()).or_default();
+        f(info);
+        if info.is_empty() {
+            self.channels.remove(&key);
+        }
+    }
+}
+
+/// `register` and `tag`, allowed to channel operators and owners.
+pub struct DirectoryCommand {
+    tag: bool,
+}
+
+impl DirectoryCommand {
+    pub fn register() -> DirectoryCommand {
+        DirectoryCommand { tag: false }
+    }
+    pub fn tag() -> DirectoryCommand {
+        DirectoryCommand { tag: true }
+    }
+}
+
+impl BotCommand for DirectoryCommand {
+    fn name(&self) -> &str {
+        if self.tag {
+            TAG_COMMAND
+        } else {
+            REGISTER_COMMAND
+        }
+    }
+    fn syntax(&self) -> &str {
+        if self.tag {
+            "tag <#channel> +tag -tag..."
+        } else {
+            "register <#channel> [desc|lang|url <value>|clear]"
+        }
+    }
+    fn summary(&self) -> &str {
+        if self.tag {
+            "adds or removes your channel tags"
+        } else {
+            "describes your channel in the directory"
+        }
+    }
+    fn help(&self, _lang: Lang) -> Vec<String> {
+        let lines: &[&str] = if self.tag {
+            &[
+                "Example:",
+                "  /msg {nick} tag #mychannel +rust +beginner -python",
+            ]
+        } else {
+            &[
+                "Without field, shows the current entry. A field without value is cleared, clear removes the entry.",
+                "Examples:",
+                "  /msg {nick} register #mychannel desc Friendly help for Rust beginners",
+                "  /msg {nick} register #mychannel lang en",
+            ]
+        };
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+    fn permission(&self) -> Permission {
+        Permission::ChannelOperator
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        let lang = ctx.lang(settings, user);
+        let reply = match run(ctx, user, args, lang) {
+            Ok(reply) => reply,
+            Err(e) => i18n::error_message(lang, &e),
+        };
+        send_reply(ctx, settings, &user.nick, reply);
+        Outcome::default()
+    }
+}
+
+fn run(ctx: &Context, user: &User, args: &[&str], lang: Lang) -> Result<String, Error> {
+    let command = args[0].to_lowercase();
+    let (channel, args) = match args {
+        [_, channel, args @ ..] if channel.starts_with('#') => (channel, args),
+        _ if command == TAG_COMMAND => return Err(localized!("tag.usage")),
+        _ => return Err(localized!("register.usage")),
+    };
+    if args.is_empty() {
+        return Ok(match ctx.network.directory.read().unwrap().get(channel) {
+            Some(info) => info.show(lang, channel),
+            None => tr!(lang, "register.none", channel),
+        });
+    }
+    if !ctx.is_channel_op(user, channel) {
+        return Err(localized!("chan.not_op", channel));
+    }
+    let update = if command == TAG_COMMAND {
+        tag_update(args, ctx.network.directory.read().unwrap().get(channel))?
+    } else {
+        register_update(args)?
+    };
+    let reply = {
+        let mut directory = ctx.network.directory.write().unwrap();
+        directory.update(channel, update);
+        match directory.get(channel) {
+            Some(info) => info.show(lang, channel),
+            None => tr!(lang, "register.removed", channel),
+        }
+    };
+    info!("{} updated {}: {}", user.hostmask, channel, args.join(" "));
+    ctx.network.save_directory()?;
+    Ok(reply)
+}
+
+type Update = Box<dyn FnOnce(&mut ChannelInfo)>;
+
+/// `desc <text>`, `lang <code>`, `url <website>`, a missing value clearing the
+/// field, or `clear` to remove the whole entry.
+fn register_update(args: &[&str]) -> Result<Update, Error> {
+    let value = if args.len() > 1 {
+        Some(args[1..].join(" "))
+    } else {
+        None
+    };
+    match args[0].to_lowercase().as_str() {
+        "desc" | "description" => {
+            if matches!(&value, Some(v) if v.chars().count() > MAX_DESCRIPTION_LEN) {
+                return Err(localized!("register.desc_len", MAX_DESCRIPTION_LEN));
+            }
+            Ok(Box::new(move |info| info.description = value))
+        }
+        "lang" | "language" => Ok(Box::new(move |info| {
+            info.language = value.map(|v| v.to_lowercase())
+        })),
+        "url" | "website" => Ok(Box::new(move |info| info.website = value)),
+        "clear" => Ok(Box::new(|info| *info = ChannelInfo::default())),
+        field => Err(localized!("register.field", field)),
+    }
+}
+
+/// `+tag` adds a tag, `-tag` removes it, a bare tag is added. Fails when the
+/// entry `info` would end up with more than `MAX_TAGS` tags.
+fn tag_update(args: &[&str], info: Option<&ChannelInfo>) -> Result<Update, Error> {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for arg in args {
+        let arg = arg.to_lowercase();
+        let (tag, add) = match arg.strip_prefix('-') {
+            Some(tag) => (tag.to_string(), false),
+            None => (arg.trim_start_matches('+').to_string(), true),
+        };
+        if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+            return Err(localized!("tag.len", MAX_TAG_LEN));
+        }
+        if add {
+            added.push(tag);
+        } else {
+            removed.push(tag);
+        }
+    }
+    let mut tags = info.map(|info| info.tags.clone()).unwrap_or_default();
+    for tag in removed.iter() {
+        tags.remove(tag);
+    }
+    tags.extend(added);
+    if tags.len() > MAX_TAGS {
+        return Err(localized!("tag.max", MAX_TAGS));
+    }
+    Ok(Box::new(move |info| info.tags = tags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn register_and_tag() {
+        let mut directory = Directory::new();
+        directory.update("#Rust", register_update(&["desc", "Rust", "help"]).unwrap());
+        directory.update("#rust", register_update(&["lang", "EN"]).unwrap());
+        let update = tag_update(&["+Rust", "beginner", "+old"], directory.get("#rust"));
+        directory.update("#rust", update.unwrap());
+        let update = tag_update(&["-old"], directory.get("#rust"));
+        directory.update("#rust", update.unwrap());
+        let info = directory.get("#RUST").unwrap();
+        assert_eq!(info.description, Some("Rust help".to_string()));
+        assert_eq!(info.language, Some("en".to_string()));
+        assert_eq!(
+            info.tags.iter().collect::<Vec<_>>(),
+            vec!["beginner", "rust"]
+        );
+        assert_eq!(
+            info.show(Lang::En, "#rust"),
+            "#rust: description: Rust help, language: en, website: (None), tags: beginner rust"
+        );
+        assert_eq!(
+            info.show(Lang::Fr, "#rust"),
+            "#rust : description : Rust help, langue : en, site web : (aucun), tags : beginner rust"
+        );
+        directory.update("#rust", register_update(&["desc"]).unwrap());
+        assert_eq!(directory.get("#rust").unwrap().description, None);
+        directory.update("#rust", register_update(&["clear"]).unwrap());
+        assert_eq!(directory.get("#rust"), None);
+    }
+    #[test]
+    fn invalid_updates() {
+        assert!(register_update(&["topic", "foo"]).is_err());
+        let long = "a".repeat(MAX_DESCRIPTION_LEN + 1);
+        assert!(register_update(&["desc", &long]).is_err());
+        assert!(tag_update(&["+"], None).is_err());
+        assert!(tag_update(&["averyveryverylongtagname"], None).is_err());
+        let tags: Vec<String> = (0..MAX_TAGS).map(|i| format!("tag{}", i)).collect();
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+        let mut info = ChannelInfo::default();
+        tag_update(&tags, None).unwrap()(&mut info);
+        let error = tag_update(&["+more"], Some(&info)).err().unwrap();
+        assert_eq!(
+            i18n::error_message(Lang::En, &error),
+            format!("Channels may have up to {} tags.", MAX_TAGS)
+        );
+        assert!(tag_update(&["-tag0", "+more"], Some(&info)).is_ok());
+    }
+    #[test]
+    fn load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("directory.toml");
+        assert_eq!(Directory::load(&path).unwrap(), Directory::new());
+        let mut directory = Directory::new();
+        directory.update(
+            "#rust",
+            register_update(&["url", "https://rust-lang.org"]).unwrap(),
+        );
+        directory.update("#rust", tag_update(&["rust"], None).unwrap());
+        directory.update("#other", register_update(&["desc", "Other"]).unwrap());
+        directory.save(&path).unwrap();
+        assert_eq!(Directory::load(&path).unwrap(), directory);
+        fs::write(&path, "[\"#broken\"]\ntags = \"rust\"\n").unwrap();
+        assert!(Directory::load(&path).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::listfile;
+use crate::{
+    listing_guard, send_replies, send_reply, BotCommand, Context, Lang, Outcome, Settings, User,
+};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const FEATURED_COMMAND: &str = "featured";
+const MAX_BLURB_LEN: usize = 200;
+
+/// A channel promoted by the owners.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Featured {
+    pub blurb: String,
+}
+
+/// Featured channels, by lowercase name.
+#[derive(Debug, Default, PartialEq)]
+pub struct FeaturedList {
+    channels: BTreeMap<String, Featured>,
+}
+
+impl FeaturedList {
+    pub fn new() -> FeaturedList {
+        FeaturedList {
+            channels: BTreeMap::new(),
+        }
+    }
+    /// Reads a TOML file with one table per channel. A missing file is an empty list.
+    pub fn load(path: &Path) -> Result<FeaturedList, Error> {
+        let content = match listfile::read(path)? {
+            Some(content) => content,
+            None => return Ok(FeaturedList::new()),
+        };
+        let channels =
+            toml::from_str(&content).map_err(|e| format_err!("{}: {}", path.display(), e))?;
+        Ok(FeaturedList { channels })
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = toml::to_string(&self.channels)?;
+        listfile::write_atomic(path, &content)
+    }
+    /// Features a channel or changes its blurb.
+    pub fn add(&mut self, channel: &str, blurb: &str) -> Result<(), Error> {
+        if blurb.chars().count() > MAX_BLURB_LEN {
+            return Err(localized!("featured.blurb_len", MAX_BLURB_LEN));
+        }
+        self.channels.insert(
+            channel.to_lowercase(),
+            Featured {
+                blurb: blurb.to_string(),
+            },
+        );
+        Ok(())
+    }
+    /// Returns false if the channel was not featured.
+    pub fn remove(&mut self, channel: &str) -> bool {
+        self.channels.remove(&channel.to_lowercase()).is_some()
+    }
+    pub fn contains(&self, channel: &str) -> bool {
+        self.channels.contains_key(&channel.to_lowercase())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+    /// Lowercase channel names and their description.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Featured)> {
+        self.channels.iter()
+    }
+}
+
+/// `featured`: lists featured channels with their current user count.
+pub struct FeaturedCommand;
+
+impl BotCommand for FeaturedCommand {
+    fn name(&self) -> &str {
+        FEATURED_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        FEATURED_COMMAND
+    }
+    fn summary(&self) -> &str {
+        "shows channels recommended by the network"
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, _args: &[&str]) -> Outcome {
+        featured_command(ctx, settings, user);
+        Outcome::default()
+    }
+}
+
+fn featured_command(ctx: &Context, settings: &Settings, user: &User) {
+    let lang = ctx.lang(settings, user);
+    let lines = featured_lines(ctx, settings, lang);
+    if lines.is_empty() {
+        send_reply(ctx, settings, &user.nick, tr!(lang, "featured.none"));
+        return;
+    }
+    send_replies(ctx, settings, &user.nick, &lines);
+}
+
+fn featured_lines(ctx: &Context, settings: &Settings, lang: Lang) -> Vec<String> {
+    /* skip the channel list fetch when nothing is featured */
+    if ctx.network.featured.read().unwrap().is_empty() {
+        return Vec::new();
+    }
+    let guard = listing_guard(ctx, settings, false);
+    let featured = ctx.network.featured.read().unwrap();
+    let optouts = ctx.network.optouts.read().unwrap();
+    featured
+        .iter()
+        .filter(|(name, _)| !optouts.contains(name))
+        .map(|(name, featured)| {
+            match guard
+                .1
+                .channels
+                .iter()
+                .find(|c| c.name.eq_ignore_ascii_case(name))
+            {
+                Some(chan) => format!(
+                    "\x02{}\x0f ({}) - {}",
+                    chan.name,
+                    tr!(lang, "featured.users", chan.users),
+                    featured.blurb
+                ),
+                None => format!("\x02{}\x0f - {}", name, featured.blurb),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feature_channels() {
+        let mut featured = FeaturedList::new();
+        featured.add("#Rust", "Official Rust channel").unwrap();
+        featured.add("#rust", "The Rust language").unwrap();
+        featured.add("#libera", "Network help").unwrap();
+        assert!(featured.contains("#RUST"));
+        let names: Vec<&String> = featured.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["#libera", "#rust"]);
+        assert_eq!(featured.channels["#rust"].blurb, "The Rust language");
+        assert!(featured.remove("#libera"));
+        assert!(!featured.remove("#libera"));
+        assert!(featured
+            .add("#long", &"a".repeat(MAX_BLURB_LEN + 1))
+            .is_err());
+    }
+    #[test]
+    fn load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("featured.toml");
+        assert!(FeaturedList::load(&path).unwrap().is_empty());
+        let mut featured = FeaturedList::new();
+        featured.add("#rust", "The Rust language").unwrap();
+        featured.save(&path).unwrap();
+        assert_eq!(FeaturedList::load(&path).unwrap(), featured);
+    }
+}
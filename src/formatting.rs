@@ -0,0 +1,277 @@
+/* a message is at most 512 bytes with its trailing CRLF */
+pub const IRC_LINE_BYTES: usize = 510;
+/* longest hostname, used until the server shows the bot host */
+const MAX_HOST_BYTES: usize = 63;
+/* usual USERLEN, with the ident "~" prefix */
+const MAX_USER_BYTES: usize = 11;
+const ELLIPSIS: &str = "…";
+
+/// Bold, color and other formatting active at some point of a line.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strikethrough: bool,
+    monospace: bool,
+    reverse: bool,
+    /// last color code, e.g. `\x0304,12`
+    color: Option<String>,
+}
+
+impl Format {
+    /// Formatting at the end of `text`.
+    fn scan(text: &str) -> Format {
+        let mut format = Format::default();
+        for unit in Units::new(text) {
+            format.apply(unit);
+        }
+        format
+    }
+    fn apply(&mut self, unit: &str) {
+        match unit {
+            "\x02" => self.bold = !self.bold,
+            "\x1d" => self.italic = !self.italic,
+            "\x1f" => self.underline = !self.underline,
+            "\x1e" => self.strikethrough = !self.strikethrough,
+            "\x11" => self.monospace = !self.monospace,
+            "\x16" => self.reverse = !self.reverse,
+            "\x0f" => *self = Format::default(),
+            "\x03" => self.color = None,
+            color if color.starts_with('\x03') => self.color = Some(color.to_string()),
+            _ => (),
+        }
+    }
+    fn toggles(&self) -> String {
+        let toggles = [
+            (self.bold, '\x02'),
+            (self.italic, '\x1d'),
+            (self.underline, '\x1f'),
+            (self.strikethrough, '\x1e'),
+            (self.monospace, '\x11'),
+            (self.reverse, '\x16'),
+        ];
+        toggles
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, code)| code)
+            .collect()
+    }
+    /// Codes restoring this formatting at the start of a line.
+    fn codes(&self) -> String {
+        let mut codes = self.toggles();
+        if let Some(color) = &self.color {
+            codes.push_str(color);
+        }
+        codes
+    }
+    /// Codes ending this formatting, toggling again what is on.
+    fn closing(&self) -> String {
+        let mut codes = self.toggles();
+        if self.color.is_some() {
+            codes.push('\x03');
+        }
+        codes
+    }
+}
+
+/// Characters and whole formatting codes of a line, which are never split.
+struct Units<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Units<'a> {
+    fn new(text: &'a str) -> Units<'a> {
+        Units { rest: text }
+    }
+}
+
+impl<'a> Iterator for Units<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let mut chars = self.rest.char_indices();
+        let (_, c) = chars.next()?;
+        let mut end = c.len_utf8();
+        /* color code: up to two digits of foreground, then ",background" */
+        if c == '\x03' {
+            end += digits(&self.rest[end..]);
+            if end > 1 && self.rest[end..].starts_with(',') {
+                let background = digits(&self.rest[end + 1..]);
+                if background > 0 {
+                    end += 1 + background;
+                }
+            }
+        }
+        let (unit, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(unit)
+    }
+}
+
+/// Length of the up to two digits starting `text`.
+fn digits(text: &str) -> usize {
+    text.bytes()
+        .take(2)
+        .take_while(|b| b.is_ascii_digit())
+        .count()
+}
+
+fn is_code(unit: &str) -> bool {
+    matches!(
+        unit.chars().next(),
+        Some('\x02' | '\x03' | '\x0f' | '\x11' | '\x16' | '\x1d' | '\x1e' | '\x1f')
+    )
+}
+
+/// Removes the IRC bold, color, italic, underline and other formatting codes.
+pub fn strip_formatting(msg: &str) -> String {
+    Units::new(msg).filter(|unit| !is_code(unit)).collect()
+}
+
+/// Bytes left for the text of a `command` sent to `target`, once the server
+/// relays it with the bot prefix: `:nick!user@host COMMAND target :text`.
+pub fn text_budget(prefix: Option<&str>, nick: &str, command: &str, target: &str) -> usize {
+    let prefix = match prefix {
+        Some(prefix) => prefix.len(),
+        None => nick.len() + 1 + MAX_USER_BYTES + 1 + MAX_HOST_BYTES,
+    };
+    let overhead = 1 + prefix + 1 + command.len() + 1 + target.len() + 2;
+    IRC_LINE_BYTES.saturating_sub(overhead)
+}
+
+/// Splits a line into lines of at most `max_bytes`, preferably between
+/// words. Continuation lines start with the formatting active at the split.
+pub fn split_line(line: &str, max_bytes: usize) -> Vec<String> {
+    if line.len() <= max_bytes {
+        return vec![line.to_string()];
+    }
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    /* formatting codes repeated at the start of the current line */
+    let mut carried = 0;
+    for unit in Units::new(line) {
+        while current.len() + unit.len() > max_bytes && current.len() > carried {
+            let split = match current[carried..].rfind(' ') {
+                Some(space) if space > 0 => carried + space,
+                _ => current.len(),
+            };
+            let format = Format::scan(&current[..split]);
+            let rest = current[split..].trim_start().to_string();
+            current.truncate(split);
+            lines.push(current.trim_end().to_string());
+            current = format.codes();
+            carried = current.len();
+            current.push_str(&rest);
+        }
+        current.push_str(unit);
+    }
+    if current.len() > carried {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Shortens `text` to at most `max_bytes` with an ellipsis, keeping whole
+/// characters and formatting codes.
+pub fn truncate(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    shorten(text, |shortened| shortened.len() <= max_bytes)
+}
+
+/// Shortens `text` to at most `max_chars` visible characters, the ellipsis
+/// included.
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if visible_len(text) <= max_chars {
+        return text.to_string();
+    }
+    shorten(text, |shortened| visible_len(shortened) <= max_chars)
+}
+
+/// Characters of `text`, formatting codes excluded.
+pub fn visible_len(text: &str) -> usize {
+    Units::new(text).filter(|unit| !is_code(unit)).count()
+}
+
+/// Longest start of `text` followed by an ellipsis that `fits`, formatting
+/// opened in it being closed so that it does not leak to what follows.
+fn shorten<F: Fn(&str) -> bool>(text: &str, fits: F) -> String {
+    let mut shortened = if fits(ELLIPSIS) {
+        ELLIPSIS.to_string()
+    } else {
+        String::new()
+    };
+    let mut kept = String::new();
+    let mut format = Format::default();
+    for unit in Units::new(text) {
+        kept.push_str(unit);
+        format.apply(unit);
+        let candidate = format!("{}{}{}", kept, format.closing(), ELLIPSIS);
+        if !fits(&candidate) {
+            break;
+        }
+        shortened = candidate;
+    }
+    shortened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_codes() {
+        assert_eq!(
+            strip_formatting("\x02[featured]\x0f #rust"),
+            "[featured] #rust"
+        );
+        assert_eq!(strip_formatting("\x0304,12red\x03 plain"), "red plain");
+        assert_eq!(strip_formatting("\x03123"), "3");
+        assert_eq!(strip_formatting("\x035,x"), ",x");
+    }
+    #[test]
+    fn split_lines() {
+        assert_eq!(split_line("short line", 20), vec!["short line"]);
+        assert_eq!(
+            split_line("one two three four", 9),
+            vec!["one two", "three", "four"]
+        );
+        /* words longer than a line are cut between characters */
+        assert_eq!(split_line("ééééé", 4), vec!["éé", "éé", "é"]);
+        /* formatting continues on the next line, codes are never cut */
+        assert_eq!(
+            split_line("\x02bold \x0304,12red\x0f end", 14),
+            vec!["\x02bold", "\x02\x0304,12red\x0f", "end"]
+        );
+        let topic = "word ".repeat(200);
+        let lines = split_line(topic.trim_end(), 100);
+        assert!(lines.iter().all(|line| line.len() <= 100));
+        assert_eq!(lines.join(" "), topic.trim_end());
+    }
+    #[test]
+    fn budget_and_truncation() {
+        let budget = text_budget(
+            Some("alis!alis@services.libera.chat"),
+            "alis",
+            "PRIVMSG",
+            "user",
+        );
+        assert_eq!(
+            budget,
+            510 - ":alis!alis@services.libera.chat PRIVMSG user :".len()
+        );
+        assert!(text_budget(None, "alis", "PRIVMSG", "user") < budget);
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("Rust programming", 10), "Rust pr…");
+        assert_eq!(truncate("ab\x0304cd", 6), "ab…");
+        assert_eq!(truncate("éééé", 6), "é…");
+        assert_eq!(truncate("ab", 1), "");
+        /* bold opened before the cut is closed */
+        assert_eq!(truncate("\x02bold\x02 text", 7), "\x02bo\x02…");
+        assert_eq!(truncate_chars("\x02bold\x02 text", 4), "\x02bol\x02…");
+        assert_eq!(truncate_chars("\x02bold\x02", 4), "\x02bold\x02");
+        assert_eq!(visible_len("\x0304,12é\x0f"), 1);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::i18n::{self, Lang};
+use crate::{build_list_app, send_reply, BotCommand, CommandRegistry, Context, Outcome};
+use crate::{Permission, Settings, User, IRC_EOL, LIST_COMMAND};
+use clap::ArgSettings;
+
+pub const HELP_COMMAND: &str = "help";
+const ADMIN_TOPIC: &str = "admin";
+
+const LIST_EXAMPLES: [&str; 4] = [
+    "  /msg {nick} list *searchterm*",
+    "  /msg {nick} list * --topic multiple*ordered*search*terms",
+    "  /msg {nick} list #foo* --min 50",
+    "  /msg {nick} list *bar? -f",
+];
+
+/// `help [command]`, generated from the registered commands.
+pub struct HelpCommand;
+
+impl BotCommand for HelpCommand {
+    fn name(&self) -> &str {
+        HELP_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "help [command]"
+    }
+    fn summary(&self) -> &str {
+        "shows the commands, or the usage of one command"
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        let nick = ctx.client.current_nickname();
+        let lang = ctx.lang(settings, user);
+        let topic = args.get(1).map(|topic| topic.to_lowercase());
+        let lines = match topic.as_deref() {
+            None => {
+                let owner = !ctx.network.owners.is_empty() && ctx.is_owner(user);
+                let mut lines = summary(&ctx.commands, settings, lang, nick, owner);
+                if !ctx.network.featured.read().unwrap().is_empty() {
+                    lines.push(tr!(lang, "help.featured", nick));
+                }
+                lines
+            }
+            Some(topic) => {
+                let command = topic.trim_start_matches(&['!', '/'][..]);
+                command_help(&ctx.commands, settings, lang, nick, command)
+                    .unwrap_or_else(|| vec![tr!(lang, "help.unknown", topic, nick)])
+            }
+        };
+        send_reply(ctx, settings, &user.nick, lines.join(IRC_EOL));
+        Outcome::default()
+    }
+}
+
+/// Syntax and summary of a command, translated when the catalog has it.
+fn usage_line(command: &dyn BotCommand, lang: Lang) -> String {
+    let summary =
+        i18n::lookup(lang, &format!("cmd.{}", command.name())).unwrap_or(command.summary());
+    format!("\x02{}\x0f {}", command.syntax(), summary)
+}
+
+/// One line per enabled command, owner commands being only hinted to owners.
+fn summary(
+    commands: &CommandRegistry,
+    settings: &Settings,
+    lang: Lang,
+    nick: &str,
+    owner: bool,
+) -> Vec<String> {
+    let mut lines = vec![tr!(lang, "help.header", nick)];
+    for command in commands.iter() {
+        if !command.is_enabled(settings) || command.permission() == Permission::Owner {
+            continue;
+        }
+        let mut line = format!("  {}", usage_line(command, lang));
+        if command.permission() == Permission::ChannelOperator {
+            line.push(' ');
+            line.push_str(&tr!(lang, "help.channel_operators"));
+        }
+        lines.push(line);
+    }
+    if owner {
+        lines.push(format!("  \x02help admin\x0f {}", tr!(lang, "help.admin")));
+    }
+    lines.push(tr!(lang, "help.footer", nick));
+    lines
+}
+
+/// Detailed usage of a command, `None` for unknown commands.
+fn command_help(
+    commands: &CommandRegistry,
+    settings: &Settings,
+    lang: Lang,
+    nick: &str,
+    name: &str,
+) -> Option<Vec<String>> {
+    let lines = if name == ADMIN_TOPIC {
+        let mut lines = vec![tr!(lang, "help.owner_commands")];
+        lines.extend(
+            commands
+                .iter()
+                .filter(|c| c.permission() == Permission::Owner && c.is_enabled(settings))
+                .map(|c| format!("  {}", usage_line(c, lang))),
+        );
+        lines
+    } else {
+        let command = commands.find(name).filter(|c| c.is_enabled(settings))?;
+        let mut lines = vec![format!(
+            "{} {}",
+            tr!(lang, "help.usage"),
+            usage_line(command, lang)
+        )];
+        if !command.aliases().is_empty() {
+            lines.push(tr!(lang, "help.aliases", command.aliases().join(", ")));
+        }
+        match i18n::lookup(lang, &format!("cmd.{}.help", command.name())) {
+            Some(help) => lines.extend(help.lines().map(|line| line.to_string())),
+            None => lines.extend(command.help(lang)),
+        }
+        lines
+    };
+    Some(
+        lines
+            .iter()
+            .map(|line| line.replace("{nick}", nick))
+            .collect(),
+    )
+}
+
+/// Options of `list`, from its clap subcommand.
+pub fn list_help(lang: Lang) -> Vec<String> {
+    let app = build_list_app();
+    let list = app.find_subcommand(LIST_COMMAND).unwrap();
+    let mut lines = vec![tr!(lang, "help.globs")];
+    for arg in list.get_arguments() {
+        let about = i18n::lookup(lang, &format!("opt.{}", arg.get_name()))
+            .or_else(|| arg.get_about())
+            .unwrap_or_default();
+        let repeated = if arg.is_set(ArgSettings::MultipleOccurrences) {
+            tr!(lang, "help.repeated")
+        } else {
+            String::new()
+        };
+        let name = match (arg.get_short(), arg.get_long()) {
+            (Some(short), Some(_)) => format!("-{}, {}", short, arg),
+            _ => arg.to_string(),
+        };
+        lines.push(format!("  \x02{}\x0f {}{}", name, about, repeated));
+    }
+    lines.push(tr!(lang, "help.examples"));
+    lines.extend(LIST_EXAMPLES.iter().map(|line| line.to_string()));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_help_from_clap() {
+        let commands = CommandRegistry::with_builtins();
+        let lines =
+            command_help(&commands, &Settings::default(), Lang::En, "alis", "list").unwrap();
+        assert_eq!(
+            lines[0],
+            "Usage: \x02list <pattern> [options]\x0f shows a list of channels matching the pattern"
+        );
+        assert!(lines.contains(&"  \x02<pattern>\x0f channel name matches pattern".to_string()));
+        assert!(lines.contains(
+            &"  \x02-t, --topic <pattern>\x0f channel topic matches pattern".to_string()
+        ));
+        assert!(lines.contains(
+            &"  \x02--min <n>\x0f shows only channels with at least <n> users".to_string()
+        ));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("  \x02--tag <tag>\x0f")
+                && line.ends_with(", can be repeated")));
+        assert!(lines.contains(&"  /msg alis list *searchterm*".to_string()));
+        assert!(lines.iter().all(|line| !line.contains("alis-bot-rs")));
+    }
+    #[test]
+    fn help_topics() {
+        let commands = CommandRegistry::with_builtins();
+        let settings = Settings::default();
+        let lines = command_help(&commands, &settings, Lang::En, "bot_", "tag").unwrap();
+        assert_eq!(
+            lines[0],
+            "Usage: \x02tag <#channel> +tag -tag...\x0f adds or removes your channel tags"
+        );
+        assert_eq!(
+            lines[2],
+            "  /msg bot_ tag #mychannel +rust +beginner -python"
+        );
+        let admin = command_help(&commands, &settings, Lang::En, "alis", "admin").unwrap();
+        assert_eq!(admin.len(), 14);
+        assert_eq!(
+            admin[1],
+            "  \x02refresh\x0f fetches the channel list from the server"
+        );
+        assert_eq!(
+            command_help(&commands, &settings, Lang::En, "alis", "unknown"),
+            None
+        );
+        assert_eq!(
+            command_help(&commands, &settings, Lang::En, "alis", "popular"),
+            None
+        );
+    }
+    #[test]
+    fn help_summary() {
+        let commands = CommandRegistry::with_builtins();
+        let mut settings = Settings::default();
+        let lines = summary(&commands, &settings, Lang::En, "alis", false);
+        assert!(lines[0].starts_with("alis allows searching for channels"));
+        assert_eq!(
+            lines[1],
+            "  \x02list <pattern> [options]\x0f shows a list of channels matching the pattern"
+        );
+        assert!(lines.contains(
+            &"  \x02optout <#channel>\x0f hides your channel from results (channel operators)"
+                .to_string()
+        ));
+        assert!(lines.iter().all(|line| !line.contains("popular")
+            && !line.contains("help admin")
+            && !line.contains("refresh")));
+        settings.public_popular = true;
+        let lines = summary(&commands, &settings, Lang::En, "alis", true);
+        assert!(lines.iter().any(|line| line.contains("\x02popular\x0f")));
+        assert!(lines.iter().any(|line| line.contains("\x02help admin\x0f")));
+        assert_eq!(
+            lines.last().unwrap(),
+            "Type /msg alis help <command> for details and examples."
+        );
+    }
+    #[test]
+    fn french_help() {
+        let commands = CommandRegistry::with_builtins();
+        let settings = Settings::default();
+        let lines = summary(&commands, &settings, Lang::Fr, "alis", false);
+        assert!(lines[0].starts_with("alis permet de rechercher des salons"));
+        assert!(lines.contains(
+            &"  \x02optout <#channel>\x0f retire votre salon des résultats (opérateurs du salon)"
+                .to_string()
+        ));
+        let lines = command_help(&commands, &settings, Lang::Fr, "alis", "list").unwrap();
+        assert!(lines.contains(&"  \x02--limit <n>\x0f affiche au plus <n> salons".to_string()));
+        assert!(lines.contains(&"Exemples :".to_string()));
+        let lines = command_help(&commands, &settings, Lang::Fr, "alis", "tag").unwrap();
+        assert_eq!(
+            lines[2],
+            "  /msg alis tag #monsalon +rust +debutant -python"
+        );
+        let lines = command_help(&commands, &settings, Lang::Fr, "alis", "admin").unwrap();
+        assert_eq!(
+            lines[1],
+            "  \x02refresh\x0f récupère la liste des salons depuis le serveur"
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Formats a catalog message, each `{}` of the message being replaced by the
+/// next argument.
+macro_rules! tr {
+    ($lang:expr, $key:expr $(, $arg:expr)*) => {
+        $crate::i18n::translate($lang, $key, &[$($arg.to_string()),*])
+    };
+}
+
+/// Builds an error whose message is translated when sent to a user.
+macro_rules! localized {
+    ($key:expr $(, $arg:expr)*) => {
+        failure::Error::from($crate::i18n::Localized::new($key, vec![$($arg.to_string()),*]))
+    };
+}
+
+/// Language of the replies.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    Fr,
+}
+
+pub const LANGS: [Lang; 2] = [Lang::En, Lang::Fr];
+
+impl FromStr for Lang {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Lang, Error> {
+        match s.to_lowercase().as_str() {
+            "en" | "english" => Ok(Lang::En),
+            "fr" | "french" | "français" | "francais" => Ok(Lang::Fr),
+            _ => Err(localized!("lang.unknown", s, "en, fr")),
+        }
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lang::En => write!(formatter, "en"),
+            Lang::Fr => write!(formatter, "fr"),
+        }
+    }
+}
+
+/// An error message from the catalogs, shown in English in logs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Localized {
+    key: &'static str,
+    args: Vec<String>,
+}
+
+impl Localized {
+    pub fn new(key: &'static str, args: Vec<String>) -> Localized {
+        Localized { key, args }
+    }
+}
+
+impl fmt::Display for Localized {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", translate(Lang::En, self.key, &self.args))
+    }
+}
+
+impl std::error::Error for Localized {}
+
+/// Message of an error in the user language, when it comes from the catalogs.
+pub fn error_message(lang: Lang, e: &Error) -> String {
+    match e.downcast_ref::<Localized>() {
+        Some(localized) => translate(lang, localized.key, &localized.args),
+        None => e.to_string(),
+    }
+}
+
+/// Message of the catalog of a language, without falling back to English.
+pub fn lookup(lang: Lang, key: &str) -> Option<&'static str> {
+    let catalog: &[(&str, &'static str)] = match lang {
+        Lang::En => &EN,
+        Lang::Fr => &FR,
+    };
+    catalog.iter().find(|(k, _)| *k == key).map(|(_, m)| *m)
+}
+
+/// Formats a message, falling back to English and then to the key itself.
+pub fn translate(lang: Lang, key: &str, args: &[String]) -> String {
+    let message = lookup(lang, key)
+        .or_else(|| lookup(Lang::En, key))
+        .unwrap_or(key);
+    let mut parts = message.split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            text.push_str(arg);
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+/* messages of both languages, keys starting with "cmd." and "opt." being the
+ * command help and the list options */
+const EN: [(&str, &str); 133] = [
+    ("introduce", "Hey {} ! {} allows searching for channels with more flexibility than the /list command. For command syntax type:\r\n/msg {} help"),
+    ("not_allowed", "You are not allowed to use this command."),
+    ("rate.slow_down", "You are sending commands too fast, slow down and retry in {}s."),
+    ("rate.ignored", "You are sending commands too fast, ignoring you for {}."),
+    ("lang.unknown", "Unknown language '{}', expected {}"),
+    ("list.invalid", "Invalid request: {}. Type /msg {} help list for the full syntax."),
+    ("list.cooldown", "The channel list was updated recently, slow down and retry in {}s, or without -f."),
+    ("list.total", "\x02Total: {} channel(s)\x0f{} matching: '{}'. Last list update was cached {} ago, run with -f to force fetching and get the most up-to-date results."),
+    ("list.truncated", " (showing first {}, use --limit to see more)"),
+    ("list.featured", "[featured]"),
+    ("parse.unknown_option", "unknown option {}"),
+    ("parse.unexpected", "unexpected argument '{}'"),
+    ("parse.empty_value", "{} expects a value"),
+    ("parse.missing_pattern", "a channel name pattern is required, e.g. list *foo*"),
+    ("parse.multiple", "{} can only be given once"),
+    ("parse.conflict", "{} cannot be used with {}"),
+    ("parse.invalid", "invalid request"),
+    ("parse.glob", "invalid glob pattern '{}': {}"),
+    ("parse.number", "--{} expects a number, got '{}'"),
+    ("help.header", "{} allows searching for channels with more flexibility than the /list command. Commands:"),
+    ("help.footer", "Type /msg {} help <command> for details and examples."),
+    ("help.featured", "New here? Have a look at our featured channels: /msg {} featured"),
+    ("help.unknown", "No help for {}. Type /msg {} help for the list of commands."),
+    ("help.usage", "Usage:"),
+    ("help.aliases", "Aliases: {}"),
+    ("help.owner_commands", "Owner commands:"),
+    ("help.admin", "owner commands"),
+    ("help.channel_operators", "(channel operators)"),
+    ("help.globs", "Patterns are case insensitive globs: * any characters, ? one character, [abc] one of a, b or c."),
+    ("help.repeated", ", can be repeated"),
+    ("help.examples", "Examples:"),
+    ("featured.none", "No channel is featured at the moment."),
+    ("featured.users", "{} users"),
+    ("chan.not_op", "You must be an operator of {} to use this command."),
+    ("optout.usage", "Usage: {} <#channel>"),
+    ("optout.done", "{} will no longer appear in results."),
+    ("optout.already", "{} is already delisted."),
+    ("optin.done", "{} will appear in results again."),
+    ("optin.not_delisted", "{} is not delisted."),
+    ("register.usage", "Usage: register <#channel> [desc|lang|url <value>] [clear]"),
+    ("tag.usage", "Usage: tag <#channel> [+tag] [-tag]..."),
+    ("register.none", "{} is not registered."),
+    ("register.removed", "{} is no longer registered."),
+    ("register.show", "{}: description: {}, language: {}, website: {}, tags: {}"),
+    ("register.none_value", "(None)"),
+    ("register.desc_len", "Descriptions are limited to {} characters."),
+    ("register.field", "Unknown field '{}', expected desc, lang, url or clear"),
+    ("tag.len", "Tags must have 1 to {} characters."),
+    ("tag.max", "Channels may have up to {} tags."),
+    ("popular.searches", "\x02{} search(es)\x0f over the last {} day(s), {} without results."),
+    ("popular.patterns", "Channel patterns: {}"),
+    ("popular.topics", "Topic patterns: {}"),
+    ("popular.channels", "Channels found: {}"),
+    ("popular.empty", "Searches without results: {}"),
+    ("set.usage", "Usage: set <setting> [value], settings: {}"),
+    ("set.unknown", "Unknown setting '{}', expected {}"),
+    ("set.lang", "Replies will now be sent in English."),
+    ("set.lang_default", "Replies will now be sent in the network language."),
+    ("set.done", "{} is now {}."),
+    ("set.reset", "{} is back to the network default."),
+    ("settings.show", "Your settings: {}. Change them with set <setting> [value]."),
+    ("settings.default", "(default)"),
+    ("value.invalid", "Invalid value '{}', expected {}"),
+    ("featured.blurb_len", "Blurbs are limited to {} characters."),
+    ("popular.none", "(None)"),
+    ("searches.too_long", "Period '{}' is too long"),
+    ("searches.invalid", "Invalid period '{}', expected a number of days (7d) or weeks (2w)"),
+    ("admin.refresh", "Channel list update requested."),
+    ("admin.reload", "Reloading configuration."),
+    ("admin.stats", "Connected for {}, {} channel(s) cached {} ago, {} request(s) handled, {} ignored mask(s)."),
+    ("admin.no_ignores", "Nobody is ignored."),
+    ("admin.ignores", "Ignored: {}"),
+    ("admin.ignored", "Now ignoring {}."),
+    ("admin.already_ignored", "{} is already ignored."),
+    ("admin.unignore_usage", "Usage: unignore <mask>"),
+    ("admin.unignored", "No longer ignoring {}."),
+    ("admin.not_ignored", "{} is not ignored."),
+    ("admin.quit", "Shutting down."),
+    ("admin.join", "Joining {}."),
+    ("admin.join_usage", "Usage: join <#channel> [key]"),
+    ("admin.part", "Leaving {}."),
+    ("admin.part_usage", "Usage: part <#channel> [message]"),
+    ("admin.say", "Message sent to {}."),
+    ("admin.say_usage", "Usage: say <target> <text>"),
+    ("admin.featured", "{} is now featured."),
+    ("admin.feature_usage", "Usage: feature <#channel> <blurb>"),
+    ("admin.unfeatured", "{} is no longer featured."),
+    ("admin.not_featured", "{} is not featured."),
+    ("admin.unfeature_usage", "Usage: unfeature <#channel>"),
+    ("suspicious.usage", "Usage: suspicious [limit]"),
+    ("suspicious.total", "\x02Total: {} suspicious channel(s)\x0f out of {}."),
+    ("suspicious.shortener", "URL shortener {}"),
+    ("suspicious.phrase", "spam phrase '{}'"),
+    ("suspicious.repeated", "{} repeated characters"),
+    ("suspicious.looks_like", "looks like {}"),
+    ("suspicious.close_to", "close to {}"),
+    ("cmd.list", "shows a list of channels matching the pattern"),
+    ("cmd.featured", "shows channels recommended by the network"),
+    ("cmd.popular", "shows the most common searches of the last days"),
+    ("cmd.optout", "hides your channel from results"),
+    ("cmd.optout.help", "Operator status is checked with a WHOIS, the channel must be visible in it."),
+    ("cmd.optin", "shows your channel in results again"),
+    ("cmd.register", "describes your channel in the directory"),
+    ("cmd.register.help", "Without field, shows the current entry. A field without value is cleared, clear removes the entry.\nExamples:\n  /msg {nick} register #mychannel desc Friendly help for Rust beginners\n  /msg {nick} register #mychannel lang en"),
+    ("cmd.tag", "adds or removes your channel tags"),
+    ("cmd.tag.help", "Example:\n  /msg {nick} tag #mychannel +rust +beginner -python"),
+    ("cmd.help", "shows the commands, or the usage of one command"),
+    ("cmd.set", "changes your settings, e.g. set lang fr"),
+    ("cmd.set.help", "lang: language of the replies, en or fr\nreply: type of the replies, privmsg or notice\nlimit: channels shown without --limit, no value for the network one\nsort: order of the results without --sort, users (most first) or name\ncolumns: channel details shown, e.g. name,users\ncolors: on or off, keeps or removes bold and colors\nhighlight: on or off, shows in bold what your patterns matched"),
+    ("cmd.settings", "shows your settings"),
+    ("cmd.refresh", "fetches the channel list from the server"),
+    ("cmd.reload", "reloads the configuration files"),
+    ("cmd.stats", "shows connection uptime, cache and request counters"),
+    ("cmd.ignore", "ignores users matching a nick!user@host mask, or lists them"),
+    ("cmd.unignore", "stops ignoring a mask"),
+    ("cmd.quit", "quits every network and stops the bot"),
+    ("cmd.join", "joins a channel"),
+    ("cmd.part", "leaves a channel"),
+    ("cmd.say", "sends a message"),
+    ("cmd.feature", "promotes a channel"),
+    ("cmd.unfeature", "stops promoting a channel"),
+    ("cmd.suspicious", "reports channels looking like spam or squatting"),
+    ("cmd.searches", "most common searches, e.g. searches 2w"),
+    ("opt.sort", "sorts channels by users (most first) or name"),
+    ("opt.show", "shows only these comma-separated columns: name, users, topic"),
+    ("opt.pattern", "channel name matches pattern"),
+    ("opt.topic", "channel topic matches pattern"),
+    ("opt.min", "shows only channels with at least <n> users"),
+    ("opt.max", "shows only channels with at most <n> users"),
+    ("opt.limit", "shows at most <n> channels"),
+    ("opt.tag", "shows only channels registered with this tag"),
+    ("opt.desc", "registered channel description matches pattern"),
+    ("opt.force", "forces a channel list update, the list is cached for a few minutes"),
+];
+
+const FR: [(&str, &str); 133] = [
+    ("introduce", "Bonjour {} ! {} permet de rechercher des salons avec plus de souplesse que la commande /list. Pour la syntaxe des commandes, tapez :\r\n/msg {} help"),
+    ("not_allowed", "Vous n'êtes pas autorisé à utiliser cette commande."),
+    ("rate.slow_down", "Vous envoyez des commandes trop vite, ralentissez et réessayez dans {} s."),
+    ("rate.ignored", "Vous envoyez des commandes trop vite, vous êtes ignoré pendant {}."),
+    ("lang.unknown", "Langue inconnue '{}', langues disponibles : {}"),
+    ("list.invalid", "Requête invalide : {}. Tapez /msg {} help list pour la syntaxe complète."),
+    ("list.cooldown", "La liste des salons a été mise à jour récemment, réessayez dans {} s, ou sans -f."),
+    ("list.total", "\x02Total : {} salon(s)\x0f{} correspondant à : '{}'. La liste a été mise en cache il y a {}, utilisez -f pour forcer sa mise à jour et obtenir les résultats les plus récents."),
+    ("list.truncated", " (affichage des {} premiers, utilisez --limit pour en voir plus)"),
+    ("list.featured", "[recommandé]"),
+    ("parse.unknown_option", "option inconnue {}"),
+    ("parse.unexpected", "argument inattendu '{}'"),
+    ("parse.empty_value", "{} attend une valeur"),
+    ("parse.missing_pattern", "un motif de nom de salon est requis, par exemple list *foo*"),
+    ("parse.multiple", "{} ne peut être donné qu'une fois"),
+    ("parse.conflict", "{} ne peut pas être utilisé avec {}"),
+    ("parse.invalid", "requête invalide"),
+    ("parse.glob", "motif glob invalide '{}' : {}"),
+    ("parse.number", "--{} attend un nombre, reçu '{}'"),
+    ("help.header", "{} permet de rechercher des salons avec plus de souplesse que la commande /list. Commandes :"),
+    ("help.footer", "Tapez /msg {} help <commande> pour les détails et des exemples."),
+    ("help.featured", "Nouveau ici ? Découvrez nos salons recommandés : /msg {} featured"),
+    ("help.unknown", "Pas d'aide pour {}. Tapez /msg {} help pour la liste des commandes."),
+    ("help.usage", "Utilisation :"),
+    ("help.aliases", "Alias : {}"),
+    ("help.owner_commands", "Commandes des propriétaires :"),
+    ("help.admin", "commandes des propriétaires"),
+    ("help.channel_operators", "(opérateurs du salon)"),
+    ("help.globs", "Les motifs sont des globs insensibles à la casse : * des caractères quelconques, ? un caractère, [abc] a, b ou c."),
+    ("help.repeated", ", peut être répété"),
+    ("help.examples", "Exemples :"),
+    ("featured.none", "Aucun salon n'est recommandé pour le moment."),
+    ("featured.users", "{} utilisateurs"),
+    ("chan.not_op", "Vous devez être opérateur de {} pour utiliser cette commande."),
+    ("optout.usage", "Utilisation : {} <#salon>"),
+    ("optout.done", "{} n'apparaîtra plus dans les résultats."),
+    ("optout.already", "{} est déjà retiré des résultats."),
+    ("optin.done", "{} apparaîtra de nouveau dans les résultats."),
+    ("optin.not_delisted", "{} n'est pas retiré des résultats."),
+    ("register.usage", "Utilisation : register <#salon> [desc|lang|url <valeur>] [clear]"),
+    ("tag.usage", "Utilisation : tag <#salon> [+tag] [-tag]..."),
+    ("register.none", "{} n'est pas enregistré."),
+    ("register.removed", "{} n'est plus enregistré."),
+    ("register.show", "{} : description : {}, langue : {}, site web : {}, tags : {}"),
+    ("register.none_value", "(aucun)"),
+    ("register.desc_len", "Les descriptions sont limitées à {} caractères."),
+    ("register.field", "Champ inconnu '{}', champs disponibles : desc, lang, url ou clear"),
+    ("tag.len", "Les tags doivent avoir de 1 à {} caractères."),
+    ("tag.max", "Les salons peuvent avoir jusqu'à {} tags."),
+    ("popular.searches", "\x02{} recherche(s)\x0f sur les {} dernier(s) jour(s), {} sans résultat."),
+    ("popular.patterns", "Motifs de nom : {}"),
+    ("popular.topics", "Motifs de topic : {}"),
+    ("popular.channels", "Salons trouvés : {}"),
+    ("popular.empty", "Recherches sans résultat : {}"),
+    ("set.usage", "Utilisation : set <réglage> [valeur], réglages : {}"),
+    ("set.unknown", "Réglage inconnu '{}', réglages disponibles : {}"),
+    ("set.lang", "Les réponses seront désormais envoyées en français."),
+    ("set.lang_default", "Les réponses seront désormais envoyées dans la langue du réseau."),
+    ("set.done", "{} vaut maintenant {}."),
+    ("set.reset", "{} revient à la valeur par défaut du réseau."),
+    ("settings.show", "Vos réglages : {}. Changez-les avec set <réglage> [valeur]."),
+    ("settings.default", "(défaut)"),
+    ("value.invalid", "Valeur '{}' invalide, valeurs possibles : {}"),
+    ("featured.blurb_len", "Les présentations sont limitées à {} caractères."),
+    ("popular.none", "(aucune)"),
+    ("searches.too_long", "La période '{}' est trop longue"),
+    ("searches.invalid", "Période '{}' invalide, attendu un nombre de jours (7d) ou de semaines (2w)"),
+    ("admin.refresh", "Mise à jour de la liste des salons demandée."),
+    ("admin.reload", "Rechargement de la configuration."),
+    ("admin.stats", "Connecté depuis {}, {} salon(s) en cache depuis {}, {} requête(s) traitée(s), {} masque(s) ignoré(s)."),
+    ("admin.no_ignores", "Personne n'est ignoré."),
+    ("admin.ignores", "Ignorés : {}"),
+    ("admin.ignored", "{} est maintenant ignoré."),
+    ("admin.already_ignored", "{} est déjà ignoré."),
+    ("admin.unignore_usage", "Utilisation : unignore <masque>"),
+    ("admin.unignored", "{} n'est plus ignoré."),
+    ("admin.not_ignored", "{} n'est pas ignoré."),
+    ("admin.quit", "Arrêt en cours."),
+    ("admin.join", "Je rejoins {}."),
+    ("admin.join_usage", "Utilisation : join <#salon> [clé]"),
+    ("admin.part", "Je quitte {}."),
+    ("admin.part_usage", "Utilisation : part <#salon> [message]"),
+    ("admin.say", "Message envoyé à {}."),
+    ("admin.say_usage", "Utilisation : say <cible> <texte>"),
+    ("admin.featured", "{} est maintenant recommandé."),
+    ("admin.feature_usage", "Utilisation : feature <#salon> <présentation>"),
+    ("admin.unfeatured", "{} n'est plus recommandé."),
+    ("admin.not_featured", "{} n'est pas recommandé."),
+    ("admin.unfeature_usage", "Utilisation : unfeature <#salon>"),
+    ("suspicious.usage", "Utilisation : suspicious [limite]"),
+    ("suspicious.total", "\x02Total : {} salon(s) suspect(s)\x0f sur {}."),
+    ("suspicious.shortener", "raccourcisseur d'URL {}"),
+    ("suspicious.phrase", "phrase de spam '{}'"),
+    ("suspicious.repeated", "{} caractères répétés"),
+    ("suspicious.looks_like", "ressemble à {}"),
+    ("suspicious.close_to", "proche de {}"),
+    ("cmd.list", "affiche les salons dont le nom correspond au motif"),
+    ("cmd.featured", "affiche les salons recommandés par le réseau"),
+    ("cmd.popular", "affiche les recherches les plus fréquentes des derniers jours"),
+    ("cmd.optout", "retire votre salon des résultats"),
+    ("cmd.optout.help", "Le statut d'opérateur est vérifié par un WHOIS, le salon doit y être visible."),
+    ("cmd.optin", "affiche de nouveau votre salon dans les résultats"),
+    ("cmd.register", "décrit votre salon dans l'annuaire"),
+    ("cmd.register.help", "Sans champ, affiche la fiche actuelle. Un champ sans valeur est effacé, clear supprime la fiche.\nExemples :\n  /msg {nick} register #monsalon desc Entraide pour débuter en Rust\n  /msg {nick} register #monsalon lang fr"),
+    ("cmd.tag", "ajoute ou retire des tags à votre salon"),
+    ("cmd.tag.help", "Exemple :\n  /msg {nick} tag #monsalon +rust +debutant -python"),
+    ("cmd.help", "affiche les commandes, ou l'utilisation d'une commande"),
+    ("cmd.set", "change vos réglages, par exemple set lang fr"),
+    ("cmd.set.help", "lang : langue des réponses, en ou fr\nreply : type des réponses, privmsg ou notice\nlimit : nombre de salons affichés sans --limit, sans valeur pour celui du réseau\nsort : ordre des résultats sans --sort, users (plus d'utilisateurs d'abord) ou name\ncolumns : détails des salons affichés, par exemple name,users\ncolors : on ou off, garde ou retire le gras et les couleurs\nhighlight : on ou off, affiche en gras ce que vos motifs ont trouvé"),
+    ("cmd.settings", "affiche vos réglages"),
+    ("cmd.refresh", "récupère la liste des salons depuis le serveur"),
+    ("cmd.reload", "recharge les fichiers de configuration"),
+    ("cmd.stats", "affiche la durée de connexion, le cache et les compteurs de requêtes"),
+    ("cmd.ignore", "ignore les utilisateurs correspondant à un masque nick!user@host, ou les liste"),
+    ("cmd.unignore", "n'ignore plus un masque"),
+    ("cmd.quit", "quitte tous les réseaux et arrête le bot"),
+    ("cmd.join", "rejoint un salon"),
+    ("cmd.part", "quitte un salon"),
+    ("cmd.say", "envoie un message"),
+    ("cmd.feature", "recommande un salon"),
+    ("cmd.unfeature", "ne recommande plus un salon"),
+    ("cmd.suspicious", "signale les salons ressemblant à du spam ou à de l'usurpation"),
+    ("cmd.searches", "recherches les plus fréquentes, par exemple searches 2w"),
+    ("opt.sort", "trie les salons : users (plus d'utilisateurs d'abord) ou name"),
+    ("opt.show", "affiche seulement ces colonnes, séparées par des virgules : name, users, topic"),
+    ("opt.pattern", "le nom du salon correspond au motif"),
+    ("opt.topic", "le topic du salon correspond au motif"),
+    ("opt.min", "affiche seulement les salons d'au moins <n> utilisateurs"),
+    ("opt.max", "affiche seulement les salons d'au plus <n> utilisateurs"),
+    ("opt.limit", "affiche au plus <n> salons"),
+    ("opt.tag", "affiche seulement les salons enregistrés avec ce tag"),
+    ("opt.desc", "la description enregistrée du salon correspond au motif"),
+    ("opt.force", "force la mise à jour de la liste des salons, gardée en cache quelques minutes"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_catalogs() {
+        for (key, message) in EN.iter() {
+            let translated =
+                lookup(Lang::Fr, key).unwrap_or_else(|| panic!("{} not translated", key));
+            assert_eq!(
+                message.matches("{}").count(),
+                translated.matches("{}").count(),
+                "{}",
+                key
+            );
+        }
+        for (key, _) in FR.iter() {
+            assert!(
+                lookup(Lang::En, key).is_some(),
+                "{} missing in English",
+                key
+            );
+        }
+        let mut keys: Vec<&str> = FR.iter().map(|(key, _)| *key).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), FR.len());
+    }
+    #[test]
+    fn translate_messages() {
+        assert_eq!(
+            tr!(Lang::En, "optout.done", "#rust"),
+            "#rust will no longer appear in results."
+        );
+        assert_eq!(
+            tr!(Lang::Fr, "list.truncated", 5),
+            " (affichage des 5 premiers, utilisez --limit pour en voir plus)"
+        );
+        /* English fallback, then the key */
+        assert_eq!(tr!(Lang::Fr, "cmd.unknown"), "cmd.unknown");
+        assert_eq!(
+            lookup(Lang::En, "cmd.list"),
+            Some("shows a list of channels matching the pattern")
+        );
+        assert_eq!(
+            tr!(Lang::En, "parse.conflict", "-a"),
+            "-a cannot be used with "
+        );
+    }
+    #[test]
+    fn localized_errors() {
+        let e: Error = "de".parse::<Lang>().unwrap_err();
+        assert_eq!(e.to_string(), "Unknown language 'de', expected en, fr");
+        assert_eq!(
+            error_message(Lang::Fr, &e),
+            "Langue inconnue 'de', langues disponibles : en, fr"
+        );
+        let e = format_err!("Cannot write file");
+        assert_eq!(error_message(Lang::Fr, &e), "Cannot write file");
+        assert_eq!("Français".parse::<Lang>().unwrap(), Lang::Fr);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::listfile;
+use failure::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Prefix of the masks matching services accounts, as in server ban masks.
+pub const ACCOUNT_PREFIX: &str = "$a:";
+
+/// Users the bot does not answer, by `nick!user@host` masks or `$a:account`
+/// services account masks.
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    /* mask, expiration of temporary ignores */
+    masks: Vec<(String, Option<Instant>)>,
+}
+
+impl IgnoreList {
+    pub fn new() -> IgnoreList {
+        IgnoreList { masks: Vec::new() }
+    }
+    /// Reads permanent masks from a file, one per line, `# ` starting a comment.
+    /// A missing file is an empty list.
+    pub fn load(path: &Path) -> Result<IgnoreList, Error> {
+        let mut list = IgnoreList::new();
+        for mask in listfile::read_lines(path)? {
+            list.add(&mask);
+        }
+        Ok(list)
+    }
+    /// Writes permanent masks to a file, temporary ignores are not kept.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let masks = self
+            .masks
+            .iter()
+            .filter(|(_, expires)| expires.is_none())
+            .map(|(mask, _)| mask);
+        listfile::write_lines(path, "ignored nick!user@host masks, one per line", masks)
+    }
+    /// Replaces permanent masks by the ones of `other`, keeping temporary ignores.
+    pub fn replace_permanent(&mut self, other: IgnoreList) {
+        self.masks.retain(|(_, expires)| expires.is_some());
+        for (mask, expires) in other.masks {
+            if !self.masks.iter().any(|(m, _)| *m == mask) {
+                self.masks.push((mask, expires));
+            }
+        }
+    }
+    /// Returns false if the mask was already ignored.
+    pub fn add(&mut self, mask: &str) -> bool {
+        self.insert(mask, None)
+    }
+    /// Ignores a mask for `duration`. Returns false if the mask was already ignored.
+    pub fn add_temporary(&mut self, mask: &str, duration: Duration) -> bool {
+        self.insert(mask, Some(Instant::now() + duration))
+    }
+    fn insert(&mut self, mask: &str, expires: Option<Instant>) -> bool {
+        self.expire();
+        let mask = normalize_mask(mask);
+        if self.masks.iter().any(|(m, _)| *m == mask) {
+            return false;
+        }
+        self.masks.push((mask, expires));
+        true
+    }
+    /// Returns false if the mask was not ignored.
+    pub fn remove(&mut self, mask: &str) -> bool {
+        self.expire();
+        let mask = normalize_mask(mask);
+        let len = self.masks.len();
+        self.masks.retain(|(m, _)| *m != mask);
+        self.masks.len() != len
+    }
+    /// Currently ignored masks.
+    pub fn masks(&self) -> Vec<&str> {
+        let now = Instant::now();
+        self.masks
+            .iter()
+            .filter(|(_, expires)| !matches!(expires, Some(e) if *e <= now))
+            .map(|(mask, _)| mask.as_str())
+            .collect()
+    }
+    /// Checks a `nick!user@host` source, and its services account when known,
+    /// against the ignored masks.
+    pub fn matches(&self, hostmask: &str, account: Option<&str>) -> bool {
+        let hostmask = hostmask.to_lowercase();
+        let account = account.map(|account| account.to_lowercase());
+        self.masks()
+            .iter()
+            .any(|mask| match mask.strip_prefix(ACCOUNT_PREFIX) {
+                Some(mask) => account.as_ref().is_some_and(|a| mask_matches(mask, a)),
+                None => mask_matches(mask, &hostmask),
+            })
+    }
+    fn expire(&mut self) {
+        let now = Instant::now();
+        self.masks
+            .retain(|(_, expires)| !matches!(expires, Some(e) if *e <= now));
+    }
+}
+
+/// Completes a partial mask, `nick` standing for `nick!*@*` and `user@host` for `*!user@host`.
+fn normalize_mask(mask: &str) -> String {
+    let mask = mask.to_lowercase();
+    if mask.starts_with(ACCOUNT_PREFIX) {
+        return mask;
+    }
+    match (mask.contains('!'), mask.contains('@')) {
+        (false, false) => format!("{}!*@*", mask),
+        (false, true) => format!("*!{}", mask),
+        (true, false) => format!("{}@*", mask),
+        (true, true) => mask,
+    }
+}
+
+/// IRC style wildcard matching, `*` for any sequence and `?` for any character.
+pub fn mask_matches(mask: &str, s: &str) -> bool {
+    let mask: Vec<char> = mask.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    let (mut m, mut i) = (0, 0);
+    /* position of the last star in mask and the matching position in s */
+    let mut backtrack: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if m < mask.len() && (mask[m] == '?' || mask[m] == s[i]) {
+            m += 1;
+            i += 1;
+        } else if m < mask.len() && mask[m] == '*' {
+            backtrack = Some((m, i));
+            m += 1;
+        } else if let Some((star, pos)) = backtrack {
+            m = star + 1;
+            i = pos + 1;
+            backtrack = Some((star, pos + 1));
+        } else {
+            return false;
+        }
+    }
+    mask[m..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn wildcard_masks() {
+        assert!(mask_matches("*!*@*", "nick!user@host"));
+        assert!(mask_matches("ni?k!*@host", "nick!user@host"));
+        assert!(mask_matches(
+            "*!*@*.example.org",
+            "nick!user@a.b.example.org"
+        ));
+        assert!(mask_matches("nick[away]!*@*", "nick[away]!user@host"));
+        assert!(!mask_matches("nick!*@*", "nick2!user@host"));
+        assert!(!mask_matches("*!*@*.example.org", "nick!user@example.net"));
+    }
+    #[test]
+    fn ignore_and_unignore() {
+        let mut ignores = IgnoreList::new();
+        assert!(ignores.add("Spammer"));
+        assert!(!ignores.add("spammer!*@*"));
+        assert!(ignores.add("*@bad.host"));
+        assert!(ignores.matches("SPAMMER!user@host", None));
+        assert!(ignores.matches("other!user@bad.host", None));
+        assert!(!ignores.matches("other!user@good.host", None));
+        assert!(ignores.remove("spammer"));
+        assert!(!ignores.remove("spammer"));
+        assert!(!ignores.matches("spammer!user@host", None));
+        assert!(ignores.add("$a:Flooder"));
+        assert!(ignores.matches("nick!user@cloak", Some("flooder")));
+        assert!(!ignores.matches("nick!user@cloak", Some("other")));
+        assert!(!ignores.matches("flooder!user@cloak", None));
+    }
+    #[test]
+    fn load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ignore.txt");
+        assert!(IgnoreList::load(&path).unwrap().masks().is_empty());
+        fs::write(
+            &path,
+            "# spammers\nspammer\n\n*!*@bad.host # flooding bot\n",
+        )
+        .unwrap();
+        let mut ignores = IgnoreList::load(&path).unwrap();
+        assert_eq!(ignores.masks(), vec!["spammer!*@*", "*!*@bad.host"]);
+        ignores.add("other");
+        ignores.add_temporary("*!*@flood.host", Duration::from_secs(60));
+        ignores.save(&path).unwrap();
+        let reloaded = IgnoreList::load(&path).unwrap();
+        assert_eq!(
+            reloaded.masks(),
+            vec!["spammer!*@*", "*!*@bad.host", "other!*@*"]
+        );
+        ignores.replace_permanent(IgnoreList::new());
+        assert_eq!(ignores.masks(), vec!["*!*@flood.host"]);
+    }
+    #[test]
+    fn temporary_ignore() {
+        let mut ignores = IgnoreList::new();
+        assert!(ignores.add_temporary("*!*@flood.host", Duration::from_secs(60)));
+        assert!(ignores.matches("nick!user@flood.host", None));
+        assert!(ignores.add_temporary("*!*@gone.host", Duration::from_secs(0)));
+        assert!(!ignores.matches("nick!user@gone.host", None));
+        assert_eq!(ignores.masks(), vec!["*!*@flood.host"]);
+    }
+}
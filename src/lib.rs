@@ -1,18 +1,62 @@
-use clap::{App, AppSettings, Arg};
+use clap::{App, AppSettings, Arg, ErrorKind};
 use failure::Error;
-use glob::Pattern;
+use glob::{MatchOptions, Pattern};
 use irc::client::prelude::*;
-use log::debug;
+use irc::proto::message::Tag;
+use log::{debug, error, warn};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::sync::{Arc, Condvar, Mutex};
+use std::ops::Range;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
 #[macro_use]
 extern crate failure;
 
+#[macro_use]
+mod i18n;
+mod admin;
+mod audit;
+mod caps;
+mod command;
+mod directory;
+mod featured;
+mod formatting;
+mod help;
+mod ignore;
+mod listfile;
+mod matcher;
+mod multiline;
+mod optout;
+mod prefs;
+mod ratelimit;
+mod searchstats;
+mod settings;
+mod suspicious;
+mod template;
+mod whois;
+pub use audit::{AuditLog, AuditRecord, RequestRecord};
+pub use caps::{bot_mode, has_tag, Caps, ACCOUNT_TAG_CAP, BOT_TAG};
+pub use command::{BotCommand, CommandRegistry, Outcome, Permission};
+pub use directory::{ChannelInfo, Directory};
+pub use featured::{Featured, FeaturedList};
+pub use i18n::Lang;
+pub use ignore::IgnoreList;
+pub use optout::OptOutList;
+pub use prefs::{PrefsStore, UserPrefs};
+pub use ratelimit::{Limited, RateLimiter, RateLimits};
+pub use searchstats::{SearchStats, Summary};
+pub use settings::{Column, ReplyType, Sasl, Server, Settings, Sort, CONNECTION_OPTIONS};
+pub use suspicious::SpamRules;
+pub use template::Template;
+pub use whois::{Whois, WhoisInfo};
+
 // bot command options
-const HELP_COMMAND: &str = "help";
+const POPULAR_COMMAND: &str = "popular";
 const LIST_COMMAND: &str = "list";
+const LIST_ABOUT: &str = "shows a list of channels matching the pattern";
 const OPT_CHAN_PATTERN: &str = "pattern";
 const OPT_TOPIC_PATTERN: &str = "topic";
 const OPT_TOPIC_PATTERN_SHORT: char = 't';
@@ -20,29 +64,17 @@ const OPT_FORCE_UPDATE_SHORT: char = 'f';
 const OPT_FORCE_UPDATE: &str = "force";
 const OPT_MAX_USERS: &str = "max";
 const OPT_MIN_USERS: &str = "min";
-// bot configuration
-const LIST_CACHE_TIME_SECS: u64 = 300; /* server list is cached for 5 min */
+const OPT_LIMIT: &str = "limit";
+const OPT_TAG: &str = "tag";
+const OPT_SORT: &str = "sort";
+const OPT_SHOW: &str = "show";
+const OPT_DESC_PATTERN: &str = "desc";
 // misc
 const IRC_EOL: &str = "\r\n";
-
-const USAGE_LIST: &'static str = "
-list-alis-bot-rs -- allows searching for channels with more flexibility than the /list command.
-Usage:
-  list <pattern> [OPTIONS]		shows a list of channels matching the pattern
-Arguments:
-  <pattern>					channel \x02name\x0f matches <pattern> (Unix shell style glob pattern)
-Options:
-  -t --topic <pattern>		channel \x02topic\x0f matches <pattern> (Unix shell style glob pattern)
-  --max <n>					shows only channels with \x02at most\x0f <n> users
-  --min <n>					shows only channels with \x02at least\x0f <n> users
-  -f 						forces channel list update. By default, channel list is cached and expires after 5 minutes
- Examples:
- /msg alis-bot-rs list *searchterm*
- /msg alis-bot-rs list * --topic multiple*ordered*search*terms
- /msg alis-bot-rs list #foo* --min 50
- /msg alis-bot-rs list *bar? -f";
-
-const INTRODUCE: &'static str = "alis-bot-rs allows searching for channels with more flexibility than the /list command. For command syntax type:\r\n/msg alis-bot-rs help\r\n";
+/* period of the public popular searches */
+const POPULAR_DAYS: u32 = 7;
+/* how long the services account of a user found with WHOIS is trusted */
+const ACCOUNT_CACHE_TIME: Duration = Duration::from_secs(300);
 
 #[derive(Debug, PartialEq)]
 struct Request {
@@ -50,7 +82,14 @@ struct Request {
     topic_pattern: Option<Pattern>,
     max_users: Option<u32>,
     min_users: Option<u32>,
+    limit: Option<u32>,
     force_update: bool,
+    /// tags the channel must be registered with
+    tags: Vec<String>,
+    desc_pattern: Option<Pattern>,
+    sort: Option<Sort>,
+    /// columns shown instead of the user or network ones
+    show: Option<Vec<Column>>,
 }
 
 impl Request {
@@ -59,76 +98,93 @@ impl Request {
         topic_pattern: Option<&str>,
         max_users: Option<&str>,
         min_users: Option<&str>,
+        limit: Option<&str>,
         force_update: bool,
     ) -> Result<Request, Error> {
         let chan_pattern = match chan_pattern {
-            Some(s) => Pattern::new(&s.to_string()).unwrap(),
+            Some(s) => parse_pattern(s)?,
             _ => return Err(format_err!("No pattern specified on channel name")),
         };
         let topic_pattern = match topic_pattern {
-            Some(s) => Some(Pattern::new(&s.to_string()).unwrap()),
-            _ => None,
-        };
-        let max_users: Option<u32> = match max_users {
-            Some(max) => parse_opt_u32(Some(max.to_string()))?,
-            _ => None,
-        };
-        let min_users: Option<u32> = match min_users {
-            Some(min) => parse_opt_u32(Some(min.to_string()))?,
+            Some(s) => Some(parse_pattern(s)?),
             _ => None,
         };
+        let max_users = parse_number(OPT_MAX_USERS, max_users)?;
+        let min_users = parse_number(OPT_MIN_USERS, min_users)?;
+        let limit = parse_number(OPT_LIMIT, limit)?;
 
         Ok(Request {
             chan_pattern,
             topic_pattern,
             max_users,
             min_users,
+            limit,
             force_update,
+            tags: Vec::new(),
+            desc_pattern: None,
+            sort: None,
+            show: None,
         })
     }
 
-    fn process(
-        &self,
-        client: &Client,
-        mutcond: &Arc<(Mutex<(bool, ChannelListing)>, Condvar)>,
-    ) -> (Vec<String>, Duration) {
-        let (ref mtx, ref cnd) = &**mutcond;
-        let expired;
-        {
-            let guard = mtx.lock().unwrap();
-            let listing = &guard.1;
-            expired = listing.has_expired();
-        }
-        if self.force_update || expired {
-            {
-                let mut guard = mtx.lock().unwrap();
-                /* listing made unavailable from now */
-                guard.0 = false;
-                let listing = &mut guard.1;
-                listing.reset();
-                send_list_command(&client);
-            }
-
-            let mut guard = mtx.lock().unwrap();
-            debug!("Waiting for channel list update...");
-            while !guard.0 {
-                guard = cnd.wait(guard).unwrap();
-            }
-        }
-        let guard = mtx.lock().unwrap();
+    /// Matching channels, the ones only shown to owners being marked
+    /// `below_floor`.
+    fn process(&self, ctx: &Context, settings: &Settings) -> (Vec<Found>, Duration) {
+        let guard = listing_guard(ctx, settings, self.force_update);
         let listing = &guard.1;
         let channels = &listing.channels;
         debug!("Processing request on {} channels", channels.len());
 
-        let result = channels
+        let optouts = ctx.network.optouts.read().unwrap();
+        let directory = ctx.network.directory.read().unwrap();
+        let featured = ctx.network.featured.read().unwrap();
+        let mut found: Vec<Found> = channels
             .iter()
-            .filter(|chan| chan.matches(&self))
-            .map(|chan| chan.to_string())
+            .filter(|chan| chan.is_visible(settings, true))
+            .filter(|chan| !optouts.contains(&chan.name))
+            .filter(|chan| self.matches_info(directory.get(&chan.name)))
+            .filter(|chan| chan.matches(self))
+            .map(|chan| Found {
+                channel: chan.clone(),
+                featured: featured.contains(&chan.name),
+                below_floor: !chan.is_visible(settings, false),
+                /* computed for the lines sent only */
+                spans: Spans::default(),
+            })
             .collect();
+        match self.sort.or(settings.default_sort) {
+            Some(Sort::Users) => found.sort_by_key(|f| Reverse(f.channel.users)),
+            Some(Sort::Name) => found.sort_by_key(|f| f.channel.name.to_lowercase()),
+            None => (),
+        }
+        let (highlighted, others): (Vec<Found>, Vec<Found>) =
+            found.into_iter().partition(|found| found.featured);
+        /* featured channels come first */
+        let result = highlighted.into_iter().chain(others).collect();
         let elapsed_time = listing.get_elapsed_time();
 
         (result, elapsed_time)
     }
+    /// Checks the tags and description search against the channel directory.
+    fn matches_info(&self, info: Option<&ChannelInfo>) -> bool {
+        if self.tags.is_empty() && self.desc_pattern.is_none() {
+            return true;
+        }
+        let info = match info {
+            Some(info) => info,
+            None => return false,
+        };
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        self.tags.iter().all(|tag| info.tags.contains(tag))
+            && match (&self.desc_pattern, &info.description) {
+                (Some(pattern), Some(desc)) => pattern.matches_with(desc, options),
+                (Some(_), None) => false,
+                (None, _) => true,
+            }
+    }
 }
 impl fmt::Display for Request {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
@@ -149,55 +205,859 @@ impl fmt::Display for Request {
         };
         write!(
             formatter,
-            "(channel name pattern: {}, topic pattern: {}, max users: {}, min users: {})",
+            "(channel name pattern: {}, topic pattern: {}, max users: {}, min users: {}",
             self.chan_pattern, topic, max, min
+        )?;
+        if !self.tags.is_empty() {
+            write!(formatter, ", tags: {}", self.tags.join(" "))?;
+        }
+        if let Some(d) = &self.desc_pattern {
+            write!(formatter, ", description pattern: {}", d)?;
+        }
+        if let Some(sort) = &self.sort {
+            write!(formatter, ", sort: {}", sort)?;
+        }
+        if let Some(show) = &self.show {
+            let show: Vec<String> = show.iter().map(|c| c.to_string()).collect();
+            write!(formatter, ", show: {}", show.join(","))?;
+        }
+        write!(formatter, ")")
+    }
+}
+
+/// Parts of a channel name and topic matched by the request patterns.
+#[derive(Debug, Default, PartialEq)]
+struct Spans {
+    name: Vec<Range<usize>>,
+    topic: Vec<Range<usize>>,
+}
+
+/// A channel matching a request.
+struct Found {
+    channel: Channel,
+    featured: bool,
+    /// under `min_visible_users`, shown to owners only
+    below_floor: bool,
+    /// highlighted in the result line
+    spans: Spans,
+}
+
+impl Found {
+    /// Result line with the chosen columns, featured channels being marked.
+    fn line(&self, lang: Lang, template: &Template, columns: &[Column]) -> String {
+        let topic = matcher::highlight(&self.channel.topic, &self.spans.topic);
+        self.line_with_topic(lang, template, columns, topic)
+    }
+    /// Result line shortened to `max_bytes` by truncating the topic, the line
+    /// being split as usual when that is not enough.
+    fn line_within(
+        &self,
+        lang: Lang,
+        template: &Template,
+        columns: &[Column],
+        max_bytes: usize,
+    ) -> String {
+        let topic = matcher::highlight(&self.channel.topic, &self.spans.topic);
+        let with_topic = |topic_bytes: usize| {
+            let shortened = formatting::truncate(&topic, topic_bytes);
+            self.line_with_topic(lang, template, columns, shortened)
+        };
+        /* longest topic fitting, the template may show it several times */
+        let (mut fits, mut too_long) = (0, topic.len());
+        while fits + 1 < too_long {
+            let middle = (fits + too_long) / 2;
+            if with_topic(middle).len() <= max_bytes {
+                fits = middle;
+            } else {
+                too_long = middle;
+            }
+        }
+        with_topic(fits)
+    }
+    fn line_with_topic(
+        &self,
+        lang: Lang,
+        template: &Template,
+        columns: &[Column],
+        topic: String,
+    ) -> String {
+        let channel = Channel {
+            name: matcher::highlight(&self.channel.name, &self.spans.name),
+            topic,
+            users: self.channel.users,
+        };
+        let line = channel.line(template, columns);
+        if self.featured {
+            format!("\x02{}\x0f {}", tr!(lang, "list.featured"), line)
+        } else {
+            line
+        }
+    }
+}
+
+/// Control requests sent by a network instance to the whole bot.
+#[derive(Debug, PartialEq)]
+pub enum BotControl {
+    Reload,
+    Shutdown(Option<String>),
+}
+
+/// Bot activity on a network, since the connection.
+pub struct Stats {
+    pub connected: Instant,
+    pub requests: u64,
+}
+
+/// Network state kept across reconnections.
+#[derive(Clone)]
+pub struct Network {
+    pub settings: Arc<RwLock<Settings>>,
+    /// services accounts allowed to use admin commands
+    pub owners: Vec<String>,
+    pub ignores: Arc<RwLock<IgnoreList>>,
+    /// channels delisted by their operators
+    pub optouts: Arc<RwLock<OptOutList>>,
+    pub directory: Arc<RwLock<Directory>>,
+    /// channels promoted by the owners
+    pub featured: Arc<RwLock<FeaturedList>>,
+    /// preferences chosen by users with `set`
+    pub prefs: Arc<RwLock<PrefsStore>>,
+    pub audit: Arc<Mutex<Option<AuditLog>>>,
+    pub searches: Arc<Mutex<SearchStats>>,
+    pub limiter: Arc<Mutex<RateLimiter>>,
+    pub control: UnboundedSender<BotControl>,
+}
+
+impl Network {
+    pub fn new(
+        settings: Settings,
+        owners: Vec<String>,
+        control: UnboundedSender<BotControl>,
+    ) -> Network {
+        let ignores = match &settings.ignore_file {
+            Some(path) => IgnoreList::load(path).unwrap_or_else(|e| {
+                error!("Cannot read ignore list: {}", e);
+                IgnoreList::new()
+            }),
+            None => IgnoreList::new(),
+        };
+        let optouts = match &settings.optout_file {
+            Some(path) => OptOutList::load(path).unwrap_or_else(|e| {
+                error!("Cannot read opt-out list: {}", e);
+                OptOutList::new()
+            }),
+            None => OptOutList::new(),
+        };
+        let directory = match &settings.directory_file {
+            Some(path) => Directory::load(path).unwrap_or_else(|e| {
+                error!("Cannot read channel directory: {}", e);
+                Directory::new()
+            }),
+            None => Directory::new(),
+        };
+        let featured = match &settings.featured_file {
+            Some(path) => FeaturedList::load(path).unwrap_or_else(|e| {
+                error!("Cannot read featured channels: {}", e);
+                FeaturedList::new()
+            }),
+            None => FeaturedList::new(),
+        };
+        let prefs = match &settings.prefs_file {
+            Some(path) => PrefsStore::load(path).unwrap_or_else(|e| {
+                error!("Cannot read user preferences: {}", e);
+                PrefsStore::new()
+            }),
+            None => PrefsStore::new(),
+        };
+        let audit = open_audit_log(&settings);
+        let searches = match &settings.search_stats_file {
+            Some(path) => SearchStats::load(path).unwrap_or_else(|e| {
+                error!("Cannot read search statistics: {}", e);
+                SearchStats::new()
+            }),
+            None => SearchStats::new(),
+        };
+        Network {
+            settings: Arc::new(RwLock::new(settings)),
+            owners,
+            ignores: Arc::new(RwLock::new(ignores)),
+            optouts: Arc::new(RwLock::new(optouts)),
+            directory: Arc::new(RwLock::new(directory)),
+            featured: Arc::new(RwLock::new(featured)),
+            prefs: Arc::new(RwLock::new(prefs)),
+            audit: Arc::new(Mutex::new(audit)),
+            searches: Arc::new(Mutex::new(searches)),
+            limiter: Arc::new(Mutex::new(RateLimiter::new())),
+            control,
+        }
+    }
+    /// Applies new settings and reads the ignore, opt-out, directory, featured
+    /// channels and preferences files again.
+    pub fn reload(&self, settings: Settings) {
+        if let Some(path) = &settings.ignore_file {
+            match IgnoreList::load(path) {
+                Ok(list) => self.ignores.write().unwrap().replace_permanent(list),
+                Err(e) => error!("Cannot read ignore list: {}", e),
+            }
+        }
+        if let Some(path) = &settings.optout_file {
+            match OptOutList::load(path) {
+                Ok(list) => *self.optouts.write().unwrap() = list,
+                Err(e) => error!("Cannot read opt-out list: {}", e),
+            }
+        }
+        if let Some(path) = &settings.directory_file {
+            match Directory::load(path) {
+                Ok(directory) => *self.directory.write().unwrap() = directory,
+                Err(e) => error!("Cannot read channel directory: {}", e),
+            }
+        }
+        if let Some(path) = &settings.featured_file {
+            match FeaturedList::load(path) {
+                Ok(featured) => *self.featured.write().unwrap() = featured,
+                Err(e) => error!("Cannot read featured channels: {}", e),
+            }
+        }
+        if let Some(path) = &settings.prefs_file {
+            match PrefsStore::load(path) {
+                Ok(prefs) => *self.prefs.write().unwrap() = prefs,
+                Err(e) => error!("Cannot read user preferences: {}", e),
+            }
+        }
+        {
+            let mut audit = self.audit.lock().unwrap();
+            let unchanged = match (&*audit, &settings.audit_log) {
+                (Some(log), Some(path)) => {
+                    log.is_same(path, settings.audit_log_max_size, settings.audit_log_files)
+                }
+                (None, None) => true,
+                _ => false,
+            };
+            if !unchanged {
+                *audit = open_audit_log(&settings);
+            }
+        }
+        *self.settings.write().unwrap() = settings;
+    }
+    /// Writes the ignore list to its file, if any.
+    pub fn save_ignores(&self) -> Result<(), Error> {
+        match &self.settings.read().unwrap().ignore_file {
+            Some(path) => self.ignores.read().unwrap().save(path),
+            None => Ok(()),
+        }
+    }
+    /// Writes the opt-out list to its file, if any.
+    pub fn save_optouts(&self) -> Result<(), Error> {
+        match &self.settings.read().unwrap().optout_file {
+            Some(path) => self.optouts.read().unwrap().save(path),
+            None => Ok(()),
+        }
+    }
+    /// Writes the channel directory to its file, if any.
+    pub fn save_directory(&self) -> Result<(), Error> {
+        match &self.settings.read().unwrap().directory_file {
+            Some(path) => self.directory.read().unwrap().save(path),
+            None => Ok(()),
+        }
+    }
+    /// Counts a handled search, the statistics being saved later by
+    /// `save_searches`.
+    fn record_search(&self, settings: &Settings, listed: &Listed) {
+        let today = chrono::Utc::now().date().naive_utc();
+        let mut searches = self.searches.lock().unwrap();
+        let topic = listed.request.topic_pattern.as_ref().map(|p| p.as_str());
+        searches.record(
+            today,
+            listed.request.chan_pattern.as_str(),
+            topic,
+            listed.results,
+            &listed.returned,
+        );
+        searches.prune(today, settings.search_stats_days);
+    }
+    /// Writes the search statistics to their file, if any, when they changed
+    /// since the last save.
+    pub fn save_searches(&self) -> Result<(), Error> {
+        match &self.settings.read().unwrap().search_stats_file {
+            Some(path) => self.searches.lock().unwrap().save_changes(path),
+            None => Ok(()),
+        }
+    }
+    /// Writes the user preferences to their file, if any.
+    pub fn save_prefs(&self) -> Result<(), Error> {
+        match &self.settings.read().unwrap().prefs_file {
+            Some(path) => self.prefs.read().unwrap().save(path),
+            None => Ok(()),
+        }
+    }
+    /// Writes the featured channels to their file, if any.
+    pub fn save_featured(&self) -> Result<(), Error> {
+        match &self.settings.read().unwrap().featured_file {
+            Some(path) => self.featured.read().unwrap().save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+fn open_audit_log(settings: &Settings) -> Option<AuditLog> {
+    let path = settings.audit_log.as_ref()?;
+    AuditLog::open(path, settings.audit_log_max_size, settings.audit_log_files)
+        .map_err(|e| error!("Cannot open audit log: {}", e))
+        .ok()
+}
+
+/// Connection state, shared by the IRC stream and the privmsg worker.
+pub struct Context {
+    pub client: Arc<Client>,
+    pub listing: Arc<(Mutex<(bool, ChannelListing)>, Condvar)>,
+    pub whois: Whois,
+    /// IRCv3 capabilities negotiated with the server
+    pub caps: Caps,
+    pub stats: Mutex<Stats>,
+    pub network: Network,
+    /// commands users can send
+    pub commands: CommandRegistry,
+    /// `nick!user@host` of the bot, as the server shows it to others
+    hostmask: RwLock<Option<String>>,
+    /* services accounts found with WHOIS, by hostmask, and when */
+    accounts: Mutex<HashMap<String, (Instant, Option<String>)>>,
+}
+
+impl Context {
+    pub fn new(client: Arc<Client>, network: Network, commands: CommandRegistry) -> Context {
+        let sasl = network.settings.read().unwrap().sasl.clone();
+        Context {
+            client,
+            listing: Arc::new((Mutex::new((false, ChannelListing::new())), Condvar::new())),
+            whois: Whois::new(),
+            caps: Caps::new(sasl),
+            stats: Mutex::new(Stats {
+                connected: Instant::now(),
+                requests: 0,
+            }),
+            network,
+            commands,
+            hostmask: RwLock::new(None),
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+    /// Learns the bot hostmask from the welcome message, its own messages
+    /// echoed by the server and host changes.
+    pub fn track_hostmask(&self, message: &Message) {
+        let nick = self.client.current_nickname();
+        let mut hostmask = self.hostmask.write().unwrap();
+        match (&message.prefix, &message.command) {
+            (_, Command::Response(Response::RPL_WELCOME, args)) => {
+                /* "Welcome to the Example IRC Network nick!user@host" */
+                let last = args.last().and_then(|text| text.split_whitespace().last());
+                if let Some(mask) = last.filter(|mask| mask.starts_with(&format!("{}!", nick))) {
+                    *hostmask = Some(mask.to_string());
+                }
+            }
+            /* <nick> <host> :is now your displayed host */
+            (_, Command::Response(Response::RPL_HOSTHIDDEN, args)) if args.len() > 1 => {
+                if let Some(mask) = hostmask.as_mut() {
+                    let user = mask.split('@').next().unwrap_or_default();
+                    *mask = format!("{}@{}", user, args[1]);
+                }
+            }
+            (Some(Prefix::Nickname(from, user, host)), command) if from == nick => {
+                let (user, host) = match command {
+                    Command::CHGHOST(user, host) => (user, host),
+                    _ => (user, host),
+                };
+                *hostmask = Some(format!("{}!{}@{}", nick, user, host));
+            }
+            _ => (),
+        }
+    }
+    /// Bytes available for the text of a reply to `target`.
+    pub fn text_budget(&self, settings: &Settings, target: &str) -> usize {
+        let command = match settings.reply_type {
+            ReplyType::Privmsg => "PRIVMSG",
+            ReplyType::Notice => "NOTICE",
+        };
+        formatting::text_budget(
+            self.hostmask.read().unwrap().as_deref(),
+            self.client.current_nickname(),
+            command,
+            target,
         )
     }
+    /// Owners are identified by their services account, taken from the message
+    /// account tag when available or asked to the server with a WHOIS.
+    pub fn is_owner(&self, user: &User) -> bool {
+        let owners = &self.network.owners;
+        if owners.is_empty() {
+            return false;
+        }
+        let account = match &user.account {
+            Some(account) => Some(account.clone()),
+            /* with account-tag, messages without the tag come from logged out users */
+            None if self.caps.enabled(ACCOUNT_TAG_CAP) => None,
+            None => self.whois_account(user),
+        };
+        match account {
+            Some(account) => owners.iter().any(|o| o.eq_ignore_ascii_case(&account)),
+            None => false,
+        }
+    }
+    /// Services account of a user asked with a WHOIS, remembered for a while
+    /// so that owner checks do not query the server on every command.
+    fn whois_account(&self, user: &User) -> Option<String> {
+        let now = Instant::now();
+        {
+            let mut accounts = self.accounts.lock().unwrap();
+            accounts.retain(|_, (checked, _)| now.duration_since(*checked) < ACCOUNT_CACHE_TIME);
+            if let Some((_, account)) = accounts.get(&user.hostmask) {
+                return account.clone();
+            }
+        }
+        let info = self.whois.query(&self.client, &user.nick)?;
+        self.accounts
+            .lock()
+            .unwrap()
+            .insert(user.hostmask.clone(), (now, info.account.clone()));
+        info.account
+    }
+    /// Network settings with the preferences of a user applied.
+    pub fn user_settings(&self, settings: &Settings, user: &User) -> Settings {
+        let mut settings = settings.clone();
+        if let Some(prefs) = self.network.prefs.read().unwrap().get(user) {
+            prefs.apply(&mut settings);
+        }
+        settings
+    }
+    /// Language of the replies to a user: their choice, or the network default.
+    pub fn lang(&self, settings: &Settings, user: &User) -> Lang {
+        self.network
+            .prefs
+            .read()
+            .unwrap()
+            .get(user)
+            .and_then(|prefs| prefs.lang)
+            .unwrap_or(settings.lang)
+    }
+    /// Channel operators, checked with a WHOIS, and owners may manage a channel
+    /// entry.
+    pub fn is_channel_op(&self, user: &User, channel: &str) -> bool {
+        match self.whois.query(&self.client, &user.nick) {
+            Some(info) if info.is_op(channel) => true,
+            _ => self.is_owner(user),
+        }
+    }
+}
+
+/// Sender of a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub nick: String,
+    /// `nick!user@host`
+    pub hostmask: String,
+    pub host: String,
+    /// services account, when the message carries an account tag
+    pub account: Option<String>,
+}
+
+impl User {
+    pub fn from_message(message: &Message) -> Option<User> {
+        let (nick, user, host) = match &message.prefix {
+            Some(Prefix::Nickname(nick, user, host)) => (nick, user, host),
+            _ => return None,
+        };
+        let account = message
+            .tags
+            .as_ref()
+            .and_then(|tags| tags.iter().find(|Tag(key, _)| key == "account"))
+            .and_then(|Tag(_, value)| value.clone());
+        Some(User {
+            nick: nick.to_string(),
+            hostmask: format!("{}!{}@{}", nick, user, host),
+            host: host.to_string(),
+            account,
+        })
+    }
+}
+
+pub fn privmsg_parse(ctx: &Context, settings: &Settings, user: &User, msg: &str) {
+    let client = &ctx.client;
+    let source = user.nick.as_str();
+    let args: Vec<&str> = msg.split_whitespace().collect();
+    let command = match args.first() {
+        Some(command) => command.to_lowercase(),
+        None => String::new(),
+    };
+
+    /* stored preferences replace the network defaults for this user */
+    let settings = &ctx.user_settings(settings, user);
+    if let Err(limited) = check_rate_limit(ctx, settings, user) {
+        send_reply(ctx, settings, source, limited);
+        return;
+    }
+
+    let started = Instant::now();
+    let found = ctx
+        .commands
+        .find(&command)
+        .filter(|c| c.is_enabled(settings));
+    let outcome = match found {
+        Some(c) if c.permission() == Permission::Owner && !ctx.is_owner(user) => {
+            warn!("Admin command denied to {}: {}", user.hostmask, msg);
+            let lang = ctx.lang(settings, user);
+            send_reply(ctx, settings, source, tr!(lang, "not_allowed"));
+            Outcome::default()
+        }
+        Some(c) => c.run(ctx, settings, user, &args),
+        None => {
+            let msg = introduce_msg(ctx.lang(settings, user), source, client.current_nickname());
+            send_reply(ctx, settings, source, msg);
+            Outcome::default()
+        }
+    };
+    let command = found.map_or(command.as_str(), |c| c.name());
+    audit(ctx, settings, user, command, &outcome, started.elapsed());
+}
+
+/// `list <pattern> [options]`
+struct ListCommand;
+
+impl BotCommand for ListCommand {
+    fn name(&self) -> &str {
+        LIST_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "list <pattern> [options]"
+    }
+    fn summary(&self) -> &str {
+        LIST_ABOUT
+    }
+    fn help(&self, lang: Lang) -> Vec<String> {
+        help::list_help(lang)
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        let listed = match list_command(ctx, settings, user, &args.join(" ")) {
+            Some(listed) => listed,
+            None => return Outcome::default(),
+        };
+        ctx.network.record_search(settings, &listed);
+        Outcome {
+            request: Some(RequestRecord::from(&listed.request)),
+            results: Some(listed.results),
+            duration: Some(listed.duration),
+            forced_refresh: listed.request.force_update,
+        }
+    }
+}
+
+/// `popular`, when enabled with `public_popular`.
+struct PopularCommand;
+
+impl BotCommand for PopularCommand {
+    fn name(&self) -> &str {
+        POPULAR_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        POPULAR_COMMAND
+    }
+    fn summary(&self) -> &str {
+        "shows the most common searches of the last days"
+    }
+    fn is_enabled(&self, settings: &Settings) -> bool {
+        settings.public_popular
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, _args: &[&str]) -> Outcome {
+        let mut summary = ctx
+            .network
+            .searches
+            .lock()
+            .unwrap()
+            .summary(chrono::Utc::now().date().naive_utc(), POPULAR_DAYS);
+        summary.channels = {
+            let (mtx, _cnd) = &*ctx.listing;
+            let guard = mtx.lock().unwrap();
+            let optouts = ctx.network.optouts.read().unwrap();
+            public_channels(&guard.1.channels, &optouts, settings, summary.channels)
+        };
+        let lang = ctx.lang(settings, user);
+        for line in searchstats::report(&summary, POPULAR_DAYS, false, lang) {
+            send_reply(ctx, settings, &user.nick, line);
+        }
+        Outcome::default()
+    }
 }
 
-pub fn privmsg_parse(
-    client: &Client,
-    mutcond: &Arc<(Mutex<(bool, ChannelListing)>, Condvar)>,
-    source: &str,
-    msg: &String,
-) -> () {
+/// Counted channels still listed publicly: not opted out since, nor hidden
+/// or below the privacy floor in the cached channel list.
+fn public_channels(
+    listing: &[Channel],
+    optouts: &OptOutList,
+    settings: &Settings,
+    counts: Vec<(String, u32)>,
+) -> Vec<(String, u32)> {
+    let visible: HashSet<String> = listing
+        .iter()
+        .filter(|chan| chan.is_visible(settings, false))
+        .map(|chan| chan.name.to_lowercase())
+        .collect();
+    counts
+        .into_iter()
+        .filter(|(name, _)| visible.contains(name) && !optouts.contains(name))
+        .collect()
+}
+
+/// A handled `list` request.
+struct Listed {
+    request: Request,
+    results: usize,
+    /// channels sent to the user
+    returned: Vec<String>,
+    duration: Duration,
+}
+
+fn list_command(ctx: &Context, settings: &Settings, user: &User, msg: &str) -> Option<Listed> {
+    let client = &ctx.client;
+    let source = user.nick.as_str();
+    let lang = ctx.lang(settings, user);
     let msg = msg.to_lowercase();
     let vec: Vec<&str> = msg.split_whitespace().collect();
+    let request = match get_request_from_args(vec) {
+        Ok(req) => req,
+        Err(e) => {
+            let msg = tr!(
+                lang,
+                "list.invalid",
+                i18n::error_message(lang, &e),
+                client.current_nickname()
+            );
+            send_reply(ctx, settings, source, msg);
+            return None;
+        }
+    };
+    if request.force_update {
+        if let Err(retry) = check_refresh_cooldown(ctx, settings) {
+            let msg = tr!(lang, "list.cooldown", retry.as_secs() + 1);
+            send_reply(ctx, settings, source, msg);
+            return None;
+        }
+    }
+    ctx.stats.lock().unwrap().requests += 1;
+    let started = Instant::now();
+    let (mut result, last_fetch) = request.process(ctx, settings);
+    let duration = started.elapsed();
+    /* owners are only exempted from the privacy floor, checked once the
+    listing is released as it may need a WHOIS */
+    if result.iter().any(|found| found.below_floor) && !ctx.is_owner(user) {
+        result.retain(|found| !found.below_floor);
+    }
+    let limit = request.limit.or(settings.default_limit);
+    let columns = request.show.as_deref().unwrap_or(&settings.columns);
+    let shown = match limit {
+        Some(limit) => result.len().min(limit as usize),
+        None => result.len(),
+    };
+    if settings.highlight {
+        for found in &mut result[..shown] {
+            found.spans = found.channel.match_spans(&request).unwrap_or_default();
+        }
+    }
+    let budget = ctx.text_budget(settings, source);
+    let mut lines: Vec<String> = result[..shown]
+        .iter()
+        .map(|found| {
+            let line = found.line(lang, &settings.template, columns);
+            if settings.truncate_topics && line.len() > budget {
+                found.line_within(lang, &settings.template, columns, budget)
+            } else {
+                line
+            }
+        })
+        .collect();
+    let truncated = if shown < result.len() {
+        tr!(lang, "list.truncated", shown)
+    } else {
+        String::new()
+    };
+    let end_msg = tr!(
+        lang,
+        "list.total",
+        result.len(),
+        truncated,
+        request,
+        format_duration(last_fetch)
+    );
+    lines.push(end_msg);
+    // Delay result to avoid anti-flooding policies
+    send_replies(ctx, settings, source, &lines);
+    debug!("{} channels matching request", &result.len());
+    Some(Listed {
+        results: result.len(),
+        /* channels shown to owners only are not counted in public statistics */
+        returned: result[..shown]
+            .iter()
+            .filter(|found| found.channel.is_visible(settings, false))
+            .map(|found| found.channel.name.clone())
+            .collect(),
+        request,
+        duration,
+    })
+}
 
-    match vec[0] {
-        LIST_COMMAND => {
-            let request = match get_request_from_args(vec) {
-                Ok(req) => req,
-                Err(_e) => {
-                    client.send_privmsg(&source, list_usage_msg()).unwrap();
-                    return;
-                }
+/// Writes a handled command to the audit log, when enabled.
+fn audit(
+    ctx: &Context,
+    settings: &Settings,
+    user: &User,
+    command: &str,
+    outcome: &Outcome,
+    duration: Duration,
+) {
+    let mut audit = ctx.network.audit.lock().unwrap();
+    let log = match &mut *audit {
+        Some(log) => log,
+        None => return,
+    };
+    let record = AuditRecord {
+        timestamp: chrono::Utc::now(),
+        network: settings.network.clone().unwrap_or_default(),
+        nick: user.nick.clone(),
+        host: user.host.clone(),
+        account: user.account.clone(),
+        command: command.to_string(),
+        request: outcome.request.clone(),
+        results: outcome.results,
+        duration_ms: outcome.duration.unwrap_or(duration).as_millis() as u64,
+        forced_refresh: outcome.forced_refresh,
+    };
+    if let Err(e) = log.write(&record) {
+        error!("Cannot write audit log: {}", e);
+    }
+}
+
+/// Applies the per user rate limits, ignoring abusers for a while. Returns the
+/// message to send to a limited user.
+fn check_rate_limit(ctx: &Context, settings: &Settings, user: &User) -> Result<(), String> {
+    let lang = ctx.lang(settings, user);
+    let limits = &settings.rate_limits;
+    let checked = ctx.network.limiter.lock().unwrap().check(
+        &user.nick,
+        user.account.as_deref(),
+        &user.host,
+        limits,
+        Instant::now(),
+    );
+    match checked {
+        Ok(()) => Ok(()),
+        Err(Limited::SlowDown(retry)) => Err(tr!(lang, "rate.slow_down", retry.as_secs() + 1)),
+        Err(Limited::Abuse) => {
+            /* a host may be shared by many users, e.g. a web client or a cloak */
+            let mask = match &user.account {
+                Some(account) => format!("{}{}", ignore::ACCOUNT_PREFIX, account),
+                None => format!("{}!*@{}", user.nick, user.host),
             };
-            let (result, last_fetch) = request.process(client, mutcond);
-            // Delay result to avoid anti-flooding policies
-            for message in &result {
-                client.send_privmsg(&source, message).unwrap();
-                thread::sleep(Duration::from_millis(1000));
+            warn!("Ignoring {} for {}s", mask, limits.ignore_time.as_secs());
+            ctx.network
+                .ignores
+                .write()
+                .unwrap()
+                .add_temporary(&mask, limits.ignore_time);
+            Err(tr!(
+                lang,
+                "rate.ignored",
+                format_duration(limits.ignore_time)
+            ))
+        }
+    }
+}
+
+/// Users can force a channel list update only once per cooldown, the server
+/// may disconnect the bot for excessive LIST usage.
+fn check_refresh_cooldown(ctx: &Context, settings: &Settings) -> Result<(), Duration> {
+    let (mtx, _cnd) = &*ctx.listing;
+    let elapsed = mtx.lock().unwrap().1.get_elapsed_time();
+    if elapsed < settings.refresh_cooldown {
+        Err(settings.refresh_cooldown - elapsed)
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the command line of a message addressed to the bot, without its
+/// command prefix. Messages sent to a channel are only considered as commands
+/// when a command prefix is configured and the message starts with it.
+pub fn command_line<'a>(settings: &Settings, msg: &'a str, private: bool) -> Option<&'a str> {
+    match &settings.command_prefix {
+        Some(prefix) if msg.starts_with(prefix.as_str()) => Some(&msg[prefix.len()..]),
+        _ if private => Some(msg),
+        _ => None,
+    }
+}
+
+/// Sends a reply to `target` using the message type configured for this
+/// network, split into lines the server relays without truncating them.
+pub fn send_reply<S: fmt::Display>(ctx: &Context, settings: &Settings, target: &str, msg: S) {
+    for line in reply_lines(ctx, settings, target, &msg.to_string()) {
+        let sent = match settings.reply_type {
+            ReplyType::Privmsg => ctx.client.send_privmsg(target, line),
+            ReplyType::Notice => ctx.client.send_notice(target, line),
+        };
+        if let Err(e) = sent {
+            error!("Cannot send reply to {}: {}", target, e);
+            return;
+        }
+    }
+}
+
+/// Sends several replies to `target`, as multiline batches when the server
+/// supports them, one by one `message_delay` apart otherwise.
+pub fn send_replies(ctx: &Context, settings: &Settings, target: &str, msgs: &[String]) {
+    let limits = match ctx.caps.value(multiline::MULTILINE_CAP) {
+        Some(value) if ctx.caps.enabled("batch") => multiline::Limits::parse(&value),
+        _ => None,
+    };
+    let limits = match limits {
+        Some(limits) => limits,
+        None => {
+            for (i, msg) in msgs.iter().enumerate() {
+                if i > 0 {
+                    thread::sleep(settings.message_delay);
+                }
+                send_reply(ctx, settings, target, msg);
             }
-            let end_msg = format!("\x02Total: {} channel(s)\x0f matching: '{}'. Last list update was cached {} ago, run with -f to force fetching and get the most up-to-date results.",
-                        &result.len(),
-                        &request,
-                        format_duration(last_fetch)
-                    );
-            client.send_privmsg(&source, end_msg).unwrap();
-            debug!("{} channels matching request", &result.len());
-        }
-        HELP_COMMAND => client.send_privmsg(&source, list_usage_msg()).unwrap(),
-        _ => client.send_privmsg(&source, introduce_msg(source)).unwrap(),
+            return;
+        }
     };
+    let lines = msgs
+        .iter()
+        .flat_map(|msg| reply_lines(ctx, settings, target, msg))
+        .collect();
+    for (i, batch) in multiline::batches(lines, &limits).iter().enumerate() {
+        if i > 0 {
+            thread::sleep(settings.message_delay);
+        }
+        for message in multiline::messages(&settings.reply_type, target, batch) {
+            if let Err(e) = ctx.client.send(message) {
+                error!("Cannot send reply to {}: {}", target, e);
+                return;
+            }
+        }
+    }
 }
 
-fn list_usage_msg() -> String {
-    USAGE_LIST.replace('\n', IRC_EOL)
+/* lines of a reply, without formatting if disabled, fitting in IRC messages */
+fn reply_lines(ctx: &Context, settings: &Settings, target: &str, msg: &str) -> Vec<String> {
+    let msg = if settings.colors {
+        msg.to_string()
+    } else {
+        formatting::strip_formatting(msg)
+    };
+    let budget = ctx.text_budget(settings, target);
+    msg.split(IRC_EOL)
+        .flat_map(|line| formatting::split_line(line, budget))
+        .collect()
 }
 
-fn introduce_msg(user_name: &str) -> String {
-    format!("Hey {} ! {}", user_name, INTRODUCE)
+fn introduce_msg(lang: Lang, user_name: &str, bot_nick: &str) -> String {
+    tr!(lang, "introduce", user_name, bot_nick, bot_nick)
 }
 
 pub struct ChannelListing {
@@ -223,9 +1083,9 @@ impl ChannelListing {
     pub fn len(&self) -> usize {
         self.channels.len()
     }
-    fn has_expired(&self) -> bool {
+    fn has_expired(&self, cache_time: Duration) -> bool {
         let now = Instant::now();
-        now.duration_since(self.last_fetch) > Duration::from_secs(LIST_CACHE_TIME_SECS)
+        now.duration_since(self.last_fetch) > cache_time
     }
     fn reset(&mut self) {
         self.channels = Vec::new();
@@ -243,7 +1103,7 @@ fn build_list_app() -> App<'static> {
         .about("allows searching for channels with more flexibility than the /list command")
         .subcommand(
             App::new(LIST_COMMAND)
-                .about("shows a list of channels matching the pattern")
+                .about(LIST_ABOUT)
                 .arg(
                     Arg::new(OPT_CHAN_PATTERN)
                         .about("channel name matches pattern")
@@ -255,25 +1115,66 @@ fn build_list_app() -> App<'static> {
                         .short(OPT_TOPIC_PATTERN_SHORT)
                         .long(OPT_TOPIC_PATTERN)
                         .takes_value(true)
+                        .value_name("pattern")
                         .about("channel topic matches pattern"),
                 )
                 .arg(
                     Arg::new(OPT_MIN_USERS)
                         .long(OPT_MIN_USERS)
                         .takes_value(true)
+                        .value_name("n")
                         .about("shows only channels with at least <n> users"),
                 )
                 .arg(
                     Arg::new(OPT_MAX_USERS)
                         .long(OPT_MAX_USERS)
                         .takes_value(true)
+                        .value_name("n")
                         .about("shows only channels with at most <n> users"),
                 )
+                .arg(
+                    Arg::new(OPT_LIMIT)
+                        .long(OPT_LIMIT)
+                        .takes_value(true)
+                        .value_name("n")
+                        .about("shows at most <n> channels"),
+                )
+                .arg(
+                    Arg::new(OPT_TAG)
+                        .long(OPT_TAG)
+                        .takes_value(true)
+                        .value_name("tag")
+                        .multiple_occurrences(true)
+                        .about("shows only channels registered with this tag"),
+                )
+                .arg(
+                    Arg::new(OPT_DESC_PATTERN)
+                        .long(OPT_DESC_PATTERN)
+                        .takes_value(true)
+                        .value_name("pattern")
+                        .about("registered channel description matches pattern"),
+                )
+                .arg(
+                    Arg::new(OPT_SORT)
+                        .long(OPT_SORT)
+                        .takes_value(true)
+                        .value_name("order")
+                        .about("sorts channels by users (most first) or name"),
+                )
+                .arg(
+                    Arg::new(OPT_SHOW)
+                        .long(OPT_SHOW)
+                        .takes_value(true)
+                        .value_name("columns")
+                        .about("shows only these comma-separated columns: name, users, topic"),
+                )
                 .arg(
                     Arg::new(OPT_FORCE_UPDATE)
                         .short(OPT_FORCE_UPDATE_SHORT)
                         .long(OPT_FORCE_UPDATE)
-                        .about("force channel list update"),
+                        .about(
+                            "forces a channel list update, the list is cached for a few minutes",
+                        ),
                 ),
         )
 }
@@ -281,7 +1182,7 @@ fn build_list_app() -> App<'static> {
 fn get_request_from_args(args: Vec<&str>) -> Result<Request, Error> {
     let matches = build_list_app().try_get_matches_from(args);
     let m = match matches {
-        Err(_e) => return Err(format_err!("Error parsing request")),
+        Err(e) => return Err(describe_parse_error(e)),
         Ok(ref matches) => match matches.subcommand() {
             Some((LIST_COMMAND, list_matches)) => list_matches,
             _ => unreachable!(),
@@ -293,11 +1194,28 @@ fn get_request_from_args(args: Vec<&str>) -> Result<Request, Error> {
         m.value_of(OPT_TOPIC_PATTERN),
         m.value_of(OPT_MAX_USERS),
         m.value_of(OPT_MIN_USERS),
+        m.value_of(OPT_LIMIT),
         m.is_present(OPT_FORCE_UPDATE),
     );
-    request
+    let mut request = request?;
+    if let Some(tags) = m.values_of(OPT_TAG) {
+        request.tags = tags
+            .map(|t| t.trim_start_matches('+').to_string())
+            .collect();
+    }
+    if let Some(desc) = m.value_of(OPT_DESC_PATTERN) {
+        request.desc_pattern = Some(parse_pattern(desc)?);
+    }
+    if let Some(sort) = m.value_of(OPT_SORT) {
+        request.sort = Some(sort.parse::<Sort>()?);
+    }
+    if let Some(show) = m.value_of(OPT_SHOW) {
+        request.show = Some(settings::parse_columns(show)?);
+    }
+    Ok(request)
 }
 
+#[derive(Clone)]
 struct Channel {
     name: String,
     topic: String,
@@ -319,30 +1237,101 @@ impl Channel {
         }
     }
     fn matches(&self, request: &Request) -> bool {
-        request.chan_pattern.matches(&self.name)
-            && match &request.topic_pattern {
-                Some(topic_pattern) => topic_pattern.matches(&self.topic),
-                None => true,
-            }
-            && match request.max_users {
-                Some(max) => self.users <= max,
-                None => true,
-            }
-            && match request.min_users {
-                Some(min) => self.users >= min,
-                None => true,
-            }
+        let users = match (request.min_users, request.max_users) {
+            (Some(min), _) if self.users < min => false,
+            (_, Some(max)) if self.users > max => false,
+            _ => true,
+        };
+        users
+            && request
+                .chan_pattern
+                .matches_with(&self.name, matcher::MATCH_OPTIONS)
+            && request.topic_pattern.as_ref().is_none_or(|topic_pattern| {
+                topic_pattern.matches_with(&self.topic, matcher::MATCH_OPTIONS)
+            })
+    }
+    /// Parts of the name and topic matched by the request patterns, `None`
+    /// when the patterns do not match.
+    fn match_spans(&self, request: &Request) -> Option<Spans> {
+        let name = matcher::match_spans(&request.chan_pattern, &self.name)?;
+        let topic = match &request.topic_pattern {
+            Some(topic_pattern) => matcher::match_spans(topic_pattern, &self.topic)?,
+            None => Vec::new(),
+        };
+        Some(Spans { name, topic })
+    }
+    /// Result line: the template when all columns are shown in their usual
+    /// order, otherwise the chosen columns with their template widths.
+    fn line(&self, template: &Template, columns: &[Column]) -> String {
+        let value = |column: Column| match column {
+            Column::Name => self.name.clone(),
+            Column::Users => self.users.to_string(),
+            Column::Topic => self.topic.clone(),
+        };
+        if columns == settings::ALL_COLUMNS {
+            return template.render(value);
+        }
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|column| template.field(*column, &value(*column)))
+            .collect();
+        fields.join(" ").trim_end().to_string()
+    }
+    /// Applies the network blocklist, and the privacy floor unless `owner` is set.
+    fn is_visible(&self, settings: &Settings, owner: bool) -> bool {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        if settings
+            .hidden_channels
+            .iter()
+            .any(|p| p.matches_with(&self.name, options))
+            || settings
+                .hidden_topics
+                .iter()
+                .any(|p| p.matches_with(&self.topic, options))
+        {
+            return false;
+        }
+        match settings.min_visible_users {
+            Some(min) if !owner => self.users >= min,
+            _ => true,
+        }
     }
 }
 
-impl fmt::Display for Channel {
-    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            formatter,
-            "{: <25} {}: {}",
-            self.name, self.users, self.topic
-        )
+/// Locks the channel list, fetching it again first when it expired or `force`
+/// is set.
+fn listing_guard<'a>(
+    ctx: &'a Context,
+    settings: &Settings,
+    force: bool,
+) -> MutexGuard<'a, (bool, ChannelListing)> {
+    let (mtx, cnd) = &*ctx.listing;
+    let expired = mtx.lock().unwrap().1.has_expired(settings.cache_time);
+    if force || expired {
+        refresh_listing(&ctx.client, &ctx.listing);
+    }
+    let mut guard = mtx.lock().unwrap();
+    if force || expired {
+        debug!("Waiting for channel list update...");
+        while !guard.0 {
+            guard = cnd.wait(guard).unwrap();
+        }
     }
+    guard
+}
+
+/// Drops the cached channel list and asks the server for a new one.
+pub fn refresh_listing(client: &Client, mutcond: &Arc<(Mutex<(bool, ChannelListing)>, Condvar)>) {
+    let (mtx, _cnd) = &**mutcond;
+    let mut guard = mtx.lock().unwrap();
+    /* listing made unavailable from now */
+    guard.0 = false;
+    let listing = &mut guard.1;
+    listing.reset();
+    send_list_command(client);
 }
 
 pub fn send_list_command(client: &Client) {
@@ -353,13 +1342,45 @@ pub fn send_list_command(client: &Client) {
 fn format_duration(duration: Duration) -> String {
     let seconds = duration.as_secs() % 60;
     let minutes = (duration.as_secs() / 60) % 60;
-    if minutes > 0 {
+    let hours = duration.as_secs() / 3600;
+    if hours > 0 {
+        format!("{}h{}min{}s", hours, minutes, seconds)
+    } else if minutes > 0 {
         format!("{}min{}s", minutes, seconds)
     } else {
         format!("{}s", seconds)
     }
 }
 
+/// Turns a clap error into a one line diagnostic.
+fn describe_parse_error(e: clap::Error) -> Error {
+    let arg = |i: usize| e.info.get(i).map(|s| s.as_str()).unwrap_or_default();
+    match e.kind {
+        ErrorKind::UnknownArgument if arg(0).starts_with('-') => {
+            localized!("parse.unknown_option", arg(0))
+        }
+        ErrorKind::UnknownArgument | ErrorKind::TooManyValues => {
+            localized!("parse.unexpected", arg(e.info.len().saturating_sub(1)))
+        }
+        ErrorKind::EmptyValue => localized!("parse.empty_value", arg(0)),
+        ErrorKind::MissingRequiredArgument => localized!("parse.missing_pattern"),
+        ErrorKind::UnexpectedMultipleUsage => localized!("parse.multiple", arg(0)),
+        ErrorKind::ArgumentConflict => localized!("parse.conflict", arg(0), arg(1)),
+        _ => localized!("parse.invalid"),
+    }
+}
+
+/// Parses a glob pattern given by a user.
+fn parse_pattern(s: &str) -> Result<Pattern, Error> {
+    Pattern::new(s).map_err(|e| localized!("parse.glob", s, e.msg))
+}
+
+/// Parses the value of a numeric option, e.g. `--min 5`.
+fn parse_number(option: &str, value: Option<&str>) -> Result<Option<u32>, Error> {
+    parse_opt_u32(value.map(|v| v.to_string()))
+        .map_err(|_| localized!("parse.number", option, value.unwrap_or_default()))
+}
+
 fn parse_opt_u32(arg: Option<String>) -> Result<Option<u32>, Error> {
     match arg {
         Some(arg) => {
@@ -375,7 +1396,8 @@ mod tests {
     use super::*;
     #[test]
     fn channel_matches_req() {
-        let request = Request::new(Some("?test*"), Some("?bar"), Some("5"), None, false).unwrap();
+        let request =
+            Request::new(Some("?test*"), Some("?bar"), Some("5"), None, None, false).unwrap();
         let matching_rpl_list = vec![
             String::from("foo"),
             String::from("#test-channel"),
@@ -411,6 +1433,127 @@ mod tests {
         let line_request = vec!["list", "*", "-t", "*", "--min", "2", "--max", "2"];
         let exact_users_request = get_request_from_args(line_request).unwrap();
         assert_eq!(matching_channel.matches(&exact_users_request), true);
+        /* patterns ignore case */
+        let line_request = vec!["list", "*rust*", "-t", "*rust*"];
+        let request = get_request_from_args(line_request).unwrap();
+        let rust_rpl_list = vec![
+            String::from("foo"),
+            String::from("#Rust"),
+            String::from("120"),
+            String::from("Rust programming"),
+        ];
+        assert!(Channel::new(&rust_rpl_list).unwrap().matches(&request));
+    }
+    #[test]
+    fn hidden_channels() {
+        let settings = Settings {
+            hidden_channels: vec![Pattern::new("#*-ops").unwrap()],
+            hidden_topics: vec![Pattern::new("*private*").unwrap()],
+            min_visible_users: Some(3),
+            ..Settings::default()
+        };
+        let channel = |name: &str, users: &str, topic: &str| {
+            Channel::new(&vec![
+                String::from("bot"),
+                String::from(name),
+                String::from(users),
+                String::from(topic),
+            ])
+            .unwrap()
+        };
+        assert!(channel("#rust", "10", "Rust").is_visible(&settings, false));
+        assert!(!channel("#Libera-OPS", "10", "").is_visible(&settings, true));
+        assert!(!channel("#secret", "10", "A Private place").is_visible(&settings, false));
+        assert!(!channel("#tiny", "2", "").is_visible(&settings, false));
+        assert!(channel("#tiny", "2", "").is_visible(&settings, true));
+        assert!(channel("#tiny", "2", "").is_visible(&Settings::default(), false));
+        /* popular channels shown to everyone */
+        let listing = vec![
+            channel("#Rust", "10", "Rust"),
+            channel("#tiny", "2", ""),
+            channel("#quiet", "10", ""),
+        ];
+        let mut optouts = OptOutList::new();
+        optouts.add("#quiet");
+        let counts = ["#rust", "#tiny", "#quiet", "#gone"]
+            .iter()
+            .map(|name| (name.to_string(), 1))
+            .collect();
+        assert_eq!(
+            public_channels(&listing, &optouts, &settings, counts),
+            vec![("#rust".to_string(), 1)]
+        );
+    }
+    #[test]
+    fn directory_search() {
+        let line_request = vec!["list", "*", "--tag", "rust", "--tag", "+beginner"];
+        let request = get_request_from_args(line_request).unwrap();
+        assert_eq!(request.tags, vec!["rust", "beginner"]);
+        assert!(!request.matches_info(None));
+        let mut info = ChannelInfo::default();
+        info.tags.insert("rust".to_string());
+        assert!(!request.matches_info(Some(&info)));
+        info.tags.insert("beginner".to_string());
+        assert!(request.matches_info(Some(&info)));
+
+        let line_request = vec!["list", "*", "--desc", "*help*"];
+        let request = get_request_from_args(line_request).unwrap();
+        assert!(!request.matches_info(Some(&info)));
+        info.description = Some("Rust HELP channel".to_string());
+        assert!(request.matches_info(Some(&info)));
+
+        let request = get_request_from_args(vec!["list", "*"]).unwrap();
+        assert!(request.matches_info(None));
+    }
+    #[test]
+    fn output_preferences() {
+        let request = get_request_from_args(vec!["list", "*", "--sort", "Users"]).unwrap();
+        assert_eq!(request.sort, Some(Sort::Users));
+        assert!(request.to_string().ends_with(", sort: users)"));
+        assert!(get_request_from_args(vec!["list", "*", "--sort", "topic"]).is_err());
+
+        let channel = Channel::new(&vec![
+            String::from("bot"),
+            String::from("#rust"),
+            String::from("10"),
+            String::from("Rust"),
+        ])
+        .unwrap();
+        let template = Template::default();
+        assert_eq!(
+            channel.line(&template, &settings::ALL_COLUMNS),
+            "#rust (10) Rust"
+        );
+        assert_eq!(
+            channel.line(&template, &[Column::Users, Column::Name]),
+            "10 #rust"
+        );
+        let template = "{name:<8}|{topic:3}".parse().unwrap();
+        assert_eq!(
+            channel.line(&template, &settings::ALL_COLUMNS),
+            "#rust   |Ru…"
+        );
+        assert_eq!(channel.line(&template, &[Column::Topic]), "Ru…");
+
+        let found = Found {
+            channel: Channel {
+                topic: "Rust programming language".to_string(),
+                ..channel.clone()
+            },
+            featured: false,
+            below_floor: false,
+            spans: Spans::default(),
+        };
+        let template = "{name} {topic} {topic}".parse().unwrap();
+        let line = found.line_within(Lang::En, &template, &settings::ALL_COLUMNS, 30);
+        assert_eq!(line, "#rust Rust pro… Rust pro…");
+        let line = found.line_within(Lang::En, &template, &settings::ALL_COLUMNS, 4);
+        assert_eq!(line, "#rust");
+
+        let request = get_request_from_args(vec!["list", "*", "--show", "topic,name"]).unwrap();
+        assert_eq!(request.show, Some(vec![Column::Topic, Column::Name]));
+        assert!(request.to_string().ends_with(", show: topic,name)"));
+        assert!(get_request_from_args(vec!["list", "*", "--show", "modes"]).is_err());
     }
     #[test]
     fn usage_examples() {
@@ -477,6 +1620,33 @@ mod tests {
         assert!(result.is_err());
     }
     #[test]
+    fn parse_error_messages() {
+        let error = |args: Vec<&str>| get_request_from_args(args).err().unwrap().to_string();
+        assert_eq!(
+            error(vec!["list", "*", "--min", "abc"]),
+            "--min expects a number, got 'abc'"
+        );
+        assert_eq!(error(vec!["list", "*", "--foo"]), "unknown option --foo");
+        assert_eq!(
+            error(vec!["list", "*", "extra"]),
+            "unexpected argument 'extra'"
+        );
+        assert_eq!(
+            error(vec!["list"]),
+            "a channel name pattern is required, e.g. list *foo*"
+        );
+        assert_eq!(
+            error(vec!["list", "["]),
+            "invalid glob pattern '[': invalid range pattern"
+        );
+        assert!(error(vec!["list", "*", "--topic", "[a"]).starts_with("invalid glob pattern '[a'"));
+        assert!(error(vec!["list", "*", "--desc", "[a"]).starts_with("invalid glob pattern"));
+        assert_eq!(
+            error(vec!["list", "*", "--max"]),
+            "--max <n> expects a value"
+        );
+    }
+    #[test]
     fn parse_success() {
         let result = parse_opt_u32(Some("1".to_string())).unwrap();
         let expected = Some(1);
@@ -496,7 +1666,8 @@ mod tests {
             channels: Vec::new(),
             last_fetch: Instant::now(),
         };
-        assert_eq!(listing.has_expired(), false);
+        let cache_time = Duration::from_secs(settings::LIST_CACHE_TIME_SECS);
+        assert_eq!(listing.has_expired(cache_time), false);
     }
     #[test]
     fn ask_new_list() {
@@ -504,7 +1675,8 @@ mod tests {
             channels: Vec::new(),
             last_fetch: Instant::now() - Duration::from_secs(301),
         };
-        assert_eq!(listing.has_expired(), true);
+        let cache_time = Duration::from_secs(settings::LIST_CACHE_TIME_SECS);
+        assert_eq!(listing.has_expired(cache_time), true);
     }
     #[test]
     fn simple_pattern_request() {
@@ -513,7 +1685,12 @@ mod tests {
             topic_pattern: None,
             max_users: None,
             min_users: None,
+            limit: None,
             force_update: false,
+            tags: Vec::new(),
+            desc_pattern: None,
+            sort: None,
+            show: None,
         };
         let line_request = vec!["list", "*test*"];
         assert_eq!(get_request_from_args(line_request).unwrap(), request);
@@ -525,7 +1702,12 @@ mod tests {
             topic_pattern: Some(Pattern::new("*other*").unwrap()),
             max_users: None,
             min_users: Some(5),
+            limit: None,
             force_update: true,
+            tags: Vec::new(),
+            desc_pattern: None,
+            sort: None,
+            show: None,
         };
         let line_request = vec!["list", "*test*", "--topic", "*other*", "--min", "5", "-f"];
         assert_eq!(get_request_from_args(line_request).unwrap(), request);
@@ -557,7 +1739,12 @@ mod tests {
             topic_pattern: Some(Pattern::new("*other*").unwrap()),
             max_users: Some(5),
             min_users: Some(2),
+            limit: None,
             force_update: true,
+            tags: Vec::new(),
+            desc_pattern: None,
+            sort: None,
+            show: None,
         };
         let line_request = vec![
             "list", "*test*", "--min=2", "--max=5", "-f", "-t", "*other*",
@@ -577,7 +1764,12 @@ mod tests {
             topic_pattern: Some(Pattern::new("*other*").unwrap()),
             max_users: None,
             min_users: Some(2),
+            limit: None,
             force_update: true,
+            tags: Vec::new(),
+            desc_pattern: None,
+            sort: None,
+            show: None,
         };
         assert_eq!(get_request_from_args(line_request).unwrap(), request);
     }
@@ -0,0 +1,97 @@
+use failure::Error;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+/// Reads the entries of a list file, one per line. A `#` followed by a space or
+/// ending the line starts a comment, so that channel names are kept. A missing
+/// file is an empty list.
+pub fn read_lines(path: &Path) -> Result<Vec<String>, Error> {
+    let content = match read(path)? {
+        Some(content) => content,
+        None => return Ok(Vec::new()),
+    };
+    Ok(content
+        .lines()
+        .map(strip_comment)
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Reads a data file, `None` when it does not exist yet.
+pub fn read(path: &Path) -> Result<Option<String>, Error> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format_err!("{}: {}", path.display(), e)),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut start = 0;
+    while let Some(i) = line[start..].find('#') {
+        let i = start + i;
+        match line[i + 1..].chars().next() {
+            Some(c) if !c.is_whitespace() => start = i + 1,
+            _ => return &line[..i],
+        }
+    }
+    line
+}
+
+/// Writes a list file, `header` being written as a comment.
+pub fn write_lines<I, S>(path: &Path, header: &str, lines: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut content = format!("# {}\n", header);
+    for line in lines {
+        content.push_str(line.as_ref());
+        content.push('\n');
+    }
+    write_atomic(path, &content)
+}
+
+/// Replaces a data file with `content`, writing and syncing a temporary file
+/// next to it first so that a failure does not lose the previous content.
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), Error> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_and_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("list.txt");
+        assert!(read_lines(&path).unwrap().is_empty());
+        fs::write(&path, "# header\none\n\n  #two # comment\n#\n").unwrap();
+        assert_eq!(read_lines(&path).unwrap(), vec!["one", "#two"]);
+        write_lines(&path, "header", ["three", "four"]).unwrap();
+        assert_eq!(read_lines(&path).unwrap(), vec!["three", "four"]);
+        assert!(!dir.path().join("list.txt.tmp").exists());
+    }
+    #[test]
+    fn same_stem_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (toml, json) = (dir.path().join("data.toml"), dir.path().join("data.json"));
+        assert_eq!(read(&toml).unwrap(), None);
+        write_atomic(&toml, "a = 1\n").unwrap();
+        write_atomic(&json, "{}").unwrap();
+        assert_eq!(read(&toml).unwrap().as_deref(), Some("a = 1\n"));
+        assert_eq!(read(&json).unwrap().as_deref(), Some("{}"));
+        assert!(!dir.path().join("data.toml.tmp").exists());
+    }
+}
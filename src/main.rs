@@ -4,12 +4,18 @@ use failure::Error;
 use futures::prelude::*;
 use glob::glob;
 use irc::client::prelude::*;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::mpsc::channel;
-use std::sync::{Arc, Condvar, Mutex};
+use std::process;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use tokio::runtime::Runtime;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep, sleep_until, timeout, Instant};
 #[macro_use]
 extern crate failure;
 
@@ -17,6 +23,13 @@ const CONFIG_FILE_OPT: &str = "config";
 const CONFIG_DIR_OPT: &str = "conf-dir";
 const CONFIG_FILE_EXT: &str = "toml";
 const DEFAULT_CONFIG_FILE: &str = "example_config.toml";
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+const QUIT_TIMEOUT: Duration = Duration::from_secs(5);
+/* the channel list is fetched anyway when NickServ does not confirm the login */
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(15);
+/* a connection lost after this delay resets the reconnection backoff */
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+const SEARCH_STATS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
 
 fn build_app() -> App<'static> {
     App::new("alis-bot-rs")
@@ -40,28 +53,298 @@ fn main() {
 
     env_logger::init();
 
-    let configs = match get_config_paths_from_cli(matches) {
+    let configs = match get_config_paths(&matches) {
         Ok(c) => c,
         Err(e) => {
             error!("{}", e);
-            match get_config_path_from_default() {
+            process::exit(1);
+        }
+    };
+
+    let rt = Runtime::new().unwrap();
+    let all_failed = rt.block_on(async move {
+        let (ended_send, mut ended) = mpsc::unbounded_channel::<InstanceEnd>();
+        let (control_send, mut control) = mpsc::unbounded_channel::<BotControl>();
+        let commands = CommandRegistry::with_builtins();
+        let mut supervisor = Supervisor::new(ended_send, control_send, commands);
+        for path in configs {
+            supervisor.spawn(path);
+        }
+        let mut hangup = signal(SignalKind::hangup()).expect("Cannot listen to SIGHUP");
+        let mut interrupt = signal(SignalKind::interrupt()).expect("Cannot listen to SIGINT");
+        let mut terminate = signal(SignalKind::terminate()).expect("Cannot listen to SIGTERM");
+        let mut quit_message = None;
+        while supervisor.is_running() {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    info!("SIGHUP received, reloading configuration");
+                    supervisor.reload(&matches);
+                }
+                _ = interrupt.recv() => {
+                    info!("SIGINT received, shutting down");
+                    break;
+                }
+                _ = terminate.recv() => {
+                    info!("SIGTERM received, shutting down");
+                    break;
+                }
+                Some(end) = ended.recv() => supervisor.ended(end),
+                Some(request) = control.recv() => match request {
+                    BotControl::Reload => {
+                        info!("Reload requested by an owner");
+                        supervisor.reload(&matches);
+                    }
+                    BotControl::Shutdown(message) => {
+                        info!("Shutdown requested by an owner");
+                        quit_message = message;
+                        break;
+                    }
+                },
+            }
+        }
+        supervisor.stop_all(quit_message);
+        /* each instance flushes its own state before ending */
+        while supervisor.is_running() {
+            match timeout(SHUTDOWN_TIMEOUT, ended.recv()).await {
+                Ok(Some(end)) => supervisor.ended(end),
+                _ => {
+                    error!("Some instances did not quit in time");
+                    break;
+                }
+            }
+        }
+        supervisor.all_failed()
+    });
+    /* do not wait for privmsg workers possibly still sleeping between messages */
+    rt.shutdown_timeout(Duration::from_secs(1));
+    if all_failed {
+        error!("All instances failed");
+        process::exit(1);
+    }
+}
+
+/// Notification sent by an instance task when it is over.
+struct InstanceEnd {
+    path: PathBuf,
+    id: u64,
+    success: bool,
+}
+
+/// Handle on a network instance running in its own task.
+struct Instance {
+    id: u64,
+    config: Config,
+    network: Network,
+    stop: oneshot::Sender<String>,
+}
+
+impl Instance {
+    fn spawn(
+        path: &PathBuf,
+        id: u64,
+        ended: mpsc::UnboundedSender<InstanceEnd>,
+        control: mpsc::UnboundedSender<BotControl>,
+        commands: CommandRegistry,
+    ) -> Result<Instance, Error> {
+        let config = Config::load(path)?;
+        let settings = Settings::from_config(&config)?;
+        let network = Network::new(settings, config.owners.clone(), control);
+        let (stop, stop_recv) = oneshot::channel::<String>();
+        let task_config = config.clone();
+        let task_network = network.clone();
+        let path = path.clone();
+        tokio::spawn(async move {
+            let result = run_network(task_config, task_network, commands, stop_recv).await;
+            if let Err(e) = &result {
+                error!("{}", e);
+            }
+            let _ = ended.send(InstanceEnd {
+                path,
+                id,
+                success: result.is_ok(),
+            });
+        });
+
+        Ok(Instance {
+            id,
+            config,
+            network,
+            stop,
+        })
+    }
+    fn stop(self, reason: &str) {
+        /* the instance task may already be over */
+        let _ = self.stop.send(reason.to_string());
+    }
+}
+
+/// Keeps track of the running instances, by configuration file.
+struct Supervisor {
+    instances: HashMap<PathBuf, Instance>,
+    /* stopped instances, not over yet */
+    stopping: usize,
+    next_id: u64,
+    ended: mpsc::UnboundedSender<InstanceEnd>,
+    control: mpsc::UnboundedSender<BotControl>,
+    /// commands offered on every network
+    commands: CommandRegistry,
+    successes: usize,
+    failures: usize,
+}
+
+impl Supervisor {
+    fn new(
+        ended: mpsc::UnboundedSender<InstanceEnd>,
+        control: mpsc::UnboundedSender<BotControl>,
+        commands: CommandRegistry,
+    ) -> Supervisor {
+        Supervisor {
+            instances: HashMap::new(),
+            stopping: 0,
+            next_id: 0,
+            ended,
+            control,
+            commands,
+            successes: 0,
+            failures: 0,
+        }
+    }
+    fn spawn(&mut self, path: PathBuf) {
+        self.next_id += 1;
+        match Instance::spawn(
+            &path,
+            self.next_id,
+            self.ended.clone(),
+            self.control.clone(),
+            self.commands.clone(),
+        ) {
+            Ok(instance) => {
+                self.instances.insert(path, instance);
+            }
+            Err(e) => {
+                error!("{}: {}", path.display(), e);
+                self.failures += 1;
+            }
+        }
+    }
+    fn stop(&mut self, path: &PathBuf, reason: &str) {
+        if let Some(instance) = self.instances.remove(path) {
+            self.stopping += 1;
+            instance.stop(reason);
+        }
+    }
+    /// Stops all instances, with their configured quit message unless one is given.
+    fn stop_all(&mut self, quit_message: Option<String>) {
+        let paths: Vec<PathBuf> = self.instances.keys().cloned().collect();
+        for path in paths {
+            let reason = match &quit_message {
+                Some(message) => message.clone(),
+                None => self.instances[&path]
+                    .network
+                    .settings
+                    .read()
+                    .unwrap()
+                    .quit_message
+                    .clone(),
+            };
+            self.stop(&path, &reason);
+        }
+    }
+    fn ended(&mut self, end: InstanceEnd) {
+        match self.instances.get(&end.path) {
+            Some(instance) if instance.id == end.id => {
+                self.instances.remove(&end.path);
+            }
+            _ => self.stopping -= 1,
+        }
+        if end.success {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+    }
+    fn is_running(&self) -> bool {
+        !self.instances.is_empty() || self.stopping > 0
+    }
+    fn all_failed(&self) -> bool {
+        self.successes == 0 && self.failures > 0 && !self.is_running()
+    }
+    fn reload(&mut self, matches: &ArgMatches) {
+        let paths = match get_config_paths(matches) {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Reload aborted: {}", e);
+                return;
+            }
+        };
+
+        let removed: Vec<PathBuf> = self
+            .instances
+            .keys()
+            .filter(|path| !paths.contains(path))
+            .cloned()
+            .collect();
+        for path in removed {
+            info!("Configuration removed: {}", path.display());
+            self.stop(&path, "Configuration removed");
+        }
+
+        for path in paths {
+            let config = match Config::load(&path) {
                 Ok(c) => c,
                 Err(e) => {
                     error!("{}", e);
-                    return;
+                    continue;
+                }
+            };
+            match self.instances.get(&path) {
+                Some(instance) if same_connection(&instance.config, &config) => {
+                    match Settings::from_config(&config) {
+                        Ok(settings) => {
+                            info!("Settings reloaded: {}", path.display());
+                            instance.network.reload(settings);
+                        }
+                        Err(e) => error!("{}: {}", path.display(), e),
+                    }
+                    continue;
                 }
+                Some(_) => {
+                    info!(
+                        "Connection settings changed, restarting: {}",
+                        path.display()
+                    );
+                    self.stop(&path, "Reconnecting");
+                }
+                None => info!("Configuration added: {}", path.display()),
             }
+            self.spawn(path);
         }
+    }
+}
+
+/// Compares two configurations, ignoring the bot settings which can be applied live.
+fn same_connection(a: &Config, b: &Config) -> bool {
+    let strip = |c: &Config| Config {
+        options: c
+            .options
+            .iter()
+            .filter(|(key, _)| CONNECTION_OPTIONS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        ..c.clone()
     };
+    strip(a) == strip(b)
+}
 
-    let rt = Runtime::new().unwrap();
-    /* tasked instances */
-    rt.block_on(async move {
-        for config in configs {
-            tokio::spawn(async move { run_instance(&config).await });
+/// Configuration files given on the command line, or the default one.
+fn get_config_paths(matches: &ArgMatches) -> Result<Vec<PathBuf>, Error> {
+    match get_config_paths_from_cli(matches.clone()) {
+        Ok(c) => Ok(c),
+        Err(e) => {
+            error!("{}", e);
+            get_config_path_from_default()
         }
-    });
-    loop {}
+    }
 }
 
 fn get_config_paths_from_cli(matches: ArgMatches) -> Result<Vec<PathBuf>, Error> {
@@ -99,7 +382,13 @@ fn get_config_paths_from_cli(matches: ArgMatches) -> Result<Vec<PathBuf>, Error>
 fn config_file_is_valid(path: PathBuf) -> Result<PathBuf, Error> {
     let error;
     if let Ok(config) = Config::load(&path) {
-        if let Some(_server) = config.server {
+        if let Err(e) = Settings::from_config(&config) {
+            error = format_err!(
+                "Configuration file: {}, {}",
+                path.as_path().display().to_string(),
+                e
+            );
+        } else if let Some(_server) = config.server {
             return Ok(path);
         } else {
             error = format_err!(
@@ -128,88 +417,348 @@ fn get_config_path_from_default() -> Result<Vec<PathBuf>, Error> {
     Ok(vec![path])
 }
 
-async fn run_instance(config: &PathBuf) -> irc::error::Result<()> {
-    let config = Config::load(&config)?;
-    let mut client = Client::from_config(config.clone()).await?;
-    client.identify()?;
-    let mut stream = client.stream()?;
-    if let Some(server) = config.server {
-        info!("Connected to {}", server);
+/// Why an instance connection is over.
+#[derive(Debug, PartialEq)]
+enum Disconnection {
+    Quit,
+    Lost,
+}
+
+/// Keeps an instance connected, reconnecting with an exponential backoff when the
+/// connection is lost. Failed attempts fail over to the next alternative server.
+async fn run_network(
+    config: Config,
+    network: Network,
+    commands: CommandRegistry,
+    mut stop: oneshot::Receiver<String>,
+) -> Result<(), Error> {
+    let mut attempts: u32 = 0;
+    let mut server_index: usize = 0;
+    loop {
+        let s = network.settings.read().unwrap().clone();
+        let network_name = s
+            .network
+            .clone()
+            .unwrap_or_else(|| config.server.clone().unwrap_or_default());
+        let mut servers = vec![alis_bot_rs::Server {
+            host: config.server.clone().unwrap_or_default(),
+            port: config.port,
+        }];
+        servers.extend(s.alt_servers.iter().cloned());
+        let count = servers.len();
+        let server = &mut servers[server_index % count];
+        server.port = server.port.or(config.port);
+        let server_config = Config {
+            server: Some(server.host.clone()),
+            port: server.port,
+            ..config.clone()
+        };
+
+        info!(
+            "{}: connecting to {} (attempt {})",
+            network_name,
+            server,
+            attempts + 1
+        );
+        let started = Instant::now();
+        match run_instance(server_config, network.clone(), commands.clone(), &mut stop).await {
+            Ok(Disconnection::Quit) => return Ok(()),
+            Ok(Disconnection::Lost) => warn!("{}: connection to {} lost", network_name, server),
+            Err(e) => warn!("{}: connection to {} failed: {}", network_name, server, e),
+        }
+        if started.elapsed() >= STABLE_CONNECTION {
+            /* retry the same server first */
+            attempts = 0;
+        } else {
+            server_index += 1;
+        }
+        attempts += 1;
+        if let Some(max) = s.reconnect_attempts {
+            if attempts > max {
+                return Err(format_err!(
+                    "{}: giving up after {} reconnection attempts",
+                    network_name,
+                    max
+                ));
+            }
+        }
+
+        let delay = backoff_delay(
+            s.reconnect_delay,
+            s.reconnect_max_delay,
+            attempts,
+            rand::random::<f64>(),
+        );
+        info!(
+            "{}: reconnecting in {:.1}s (attempt {})",
+            network_name,
+            delay.as_secs_f32(),
+            attempts + 1
+        );
+        tokio::select! {
+            _ = sleep(delay) => (),
+            _ = &mut stop => return Ok(()),
+        }
     }
+}
+
+/// Delay before the nth reconnection attempt: `base` doubled on each attempt, up to
+/// `max`, then randomly reduced by up to a half so instances do not reconnect together.
+/// `jitter` is expected in [0, 1).
+fn backoff_delay(base: Duration, max: Duration, attempt: u32, jitter: f64) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let delay = base.checked_mul(1 << exponent).unwrap_or(max).min(max);
+    delay.mul_f64(0.5 + jitter / 2.0)
+}
+
+/// Registers with the server after asking for its IRCv3 capabilities, the
+/// registration being held until the negotiation ends with `CAP END`.
+fn register(client: &Client, config: &Config) -> irc::error::Result<()> {
+    client.send_cap_ls(NegotiationVersion::V302)?;
+    if !config.password().is_empty() {
+        client.send(Command::PASS(config.password().to_string()))?;
+    }
+    client.send(Command::NICK(config.nickname()?.to_string()))?;
+    client.send(Command::USER(
+        config.username().to_string(),
+        "0".to_string(),
+        config.real_name().to_string(),
+    ))
+}
+
+async fn run_instance(
+    config: Config,
+    network: Network,
+    commands: CommandRegistry,
+    stop: &mut oneshot::Receiver<String>,
+) -> irc::error::Result<Disconnection> {
+    let sasl = network.settings.read().unwrap().sasl.is_some();
+    /* NickServ identification is sent after the MOTD, replaced by SASL when enabled */
+    let nickserv = !sasl && !config.nick_password().is_empty();
+    let client_config = Config {
+        nick_password: if sasl {
+            None
+        } else {
+            config.nick_password.clone()
+        },
+        ..config.clone()
+    };
+    let mut client = Client::from_config(client_config).await?;
+    register(&client, &config)?;
+    let mut stream = client.stream()?;
+    let server = config.server.clone().unwrap_or_default();
+    info!("Connected to {}", server);
 
     let mut server_name: Option<String> = None;
-    let listing = ChannelListing::new();
 
     // private messages mpsc channel
     let (ms, mr) = channel::<Message>();
-    // shared client
+    // shared client and connection state
     let client = Arc::new(client);
-    let privmsg_client = Arc::clone(&client);
+    let ctx = Arc::new(Context::new(Arc::clone(&client), network, commands));
+    let privmsg_ctx = Arc::clone(&ctx);
     // Mutex with condition for listing access
-    let mutcond: Arc<(Mutex<(bool, ChannelListing)>, Condvar)> =
-        Arc::new((Mutex::new((false, listing)), Condvar::new()));
-    let c_mutcond = Arc::clone(&mutcond);
-
-    let privmsg_thread = thread::spawn(move || loop {
-        let message = mr.recv().unwrap();
-        if let Command::PRIVMSG(_target, msg) = &message.command {
-            let source = match message.source_nickname() {
-                Some(s) => s,
-                None => continue,
-            };
-            privmsg_parse(&privmsg_client, &c_mutcond, &source, &msg);
+    let mutcond = Arc::clone(&ctx.listing);
+
+    let privmsg_thread = thread::spawn(move || {
+        /* search statistics are saved from time to time rather than on every search */
+        let mut searches_saved = std::time::Instant::now();
+        loop {
+            match mr.recv_timeout(SEARCH_STATS_SAVE_INTERVAL) {
+                Ok(message) => handle_privmsg(&privmsg_ctx, &message),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if searches_saved.elapsed() >= SEARCH_STATS_SAVE_INTERVAL {
+                save_searches(&privmsg_ctx.network);
+                searches_saved = std::time::Instant::now();
+            }
         }
+        save_searches(&privmsg_ctx.network);
     });
 
-    while let Some(message) = stream.next().await.transpose()? {
-        match &message.command {
-            Command::PRIVMSG(target, _msg) => {
-                // responds only to private message, ignoring unspecified source and server messages
-                if target.eq(&client.current_nickname()) {
-                    let source = if let Some(s) = message.source_nickname() {
-                        s
-                    } else {
-                        continue;
+    let mut quit_deadline: Option<Instant> = None;
+    /* first channel list delayed until NickServ logs the bot in */
+    let mut login_deadline: Option<Instant> = None;
+    /* errors end the loop without skipping the cleanup below */
+    let served: irc::error::Result<()> = async {
+        loop {
+            /* unset deadlines are never awaited */
+            let quit_at = quit_deadline.unwrap_or_else(Instant::now);
+            let login_at = login_deadline.unwrap_or_else(Instant::now);
+            let message = tokio::select! {
+                message = stream.next() => match message.transpose()? {
+                    Some(m) => m,
+                    None => break,
+                },
+                reason = &mut *stop, if quit_deadline.is_none() => {
+                    info!("Leaving {}", server);
+                    /* the stop request is consumed: set first, a failed QUIT is still a quit */
+                    quit_deadline = Some(Instant::now() + QUIT_TIMEOUT);
+                    /* keep polling the stream until the server closes the connection */
+                    client.send_quit(reason.unwrap_or_default())?;
+                    continue;
+                }
+                _ = sleep_until(quit_at), if quit_deadline.is_some() => {
+                    debug!("No answer to QUIT from {}", server);
+                    break;
+                }
+                _ = sleep_until(login_at), if login_deadline.is_some() => {
+                    warn!("No login confirmation from {}, fetching the channel list anyway", server);
+                    login_deadline = None;
+                    send_list_command(&client);
+                    continue;
+                }
+            };
+            ctx.whois.handle(&message);
+            ctx.track_hostmask(&message);
+            for reply in ctx.caps.handle(&message) {
+                client.send(reply)?;
+            }
+            match &message.command {
+                Command::PRIVMSG(target, msg) => {
+                    // responds only to private message or prefixed channel commands,
+                    // ignoring unspecified source and server messages
+                    let prefixed = match &ctx.network.settings.read().unwrap().command_prefix {
+                        Some(prefix) => {
+                            target.is_channel_name() && msg.starts_with(prefix.as_str())
+                        }
+                        None => false,
                     };
-                    match &server_name {
-                        Some(server_name) if source.eq(server_name) => continue,
-                        _ => ms.send(message).unwrap(),
+                    if target.eq(&client.current_nickname()) || prefixed {
+                        let source = if let Some(s) = message.source_nickname() {
+                            s
+                        } else {
+                            continue;
+                        };
+                        match &server_name {
+                            Some(server_name) if source.eq(server_name) => continue,
+                            _ => (),
+                        }
+                        /* own messages sent back by echo-message, and other bots */
+                        if source.eq(client.current_nickname()) || has_tag(&message, BOT_TAG) {
+                            continue;
+                        }
+                        if let Some(user) = User::from_message(&message) {
+                            if ctx.network.ignores.read().unwrap().matches(&user.hostmask, user.account.as_deref()) {
+                                debug!("Ignoring message from {}", user.hostmask);
+                                continue;
+                            }
+                        }
+                        /* a panicked worker is restarted with the connection */
+                        if ms.send(message).is_err() {
+                            error!("Command worker of {} stopped, reconnecting", server);
+                            break;
+                        }
                     }
                 }
-            }
-            Command::Response(rpl_type, v) if *rpl_type == Response::RPL_LIST => {
-                /* updating channel list */
-                let &(ref mtx, ref _cnd) = &*mutcond;
-                let mut guard = mtx.lock().unwrap();
-                let listing = &mut guard.1;
-                listing.add_channel(v);
-            }
-            Command::Response(rpl_type, _v) if *rpl_type == Response::RPL_LISTEND => {
-                let &(ref mtx, ref cnd) = &*mutcond;
-                let mut guard = mtx.lock().unwrap();
-                let listing = &mut guard.1;
-                listing.set_timestamp();
-                debug!(
-                    "Channel list request...done. {} channels received",
-                    &listing.len()
-                );
-                /* listing made available from now */
-                guard.0 = true;
-                cnd.notify_all();
-            }
-            Command::Response(rpl_type, _) if *rpl_type == Response::RPL_WELCOME => {
-                if let Some(Prefix::ServerName(name)) = &message.prefix {
-                    server_name = Some(name.to_string());
+                Command::Response(rpl_type, v) if *rpl_type == Response::RPL_LIST => {
+                    /* updating channel list */
+                    let &(ref mtx, ref _cnd) = &*mutcond;
+                    let mut guard = mtx.lock().unwrap();
+                    let listing = &mut guard.1;
+                    listing.add_channel(v);
+                }
+                Command::Response(rpl_type, _v) if *rpl_type == Response::RPL_LISTEND => {
+                    let &(ref mtx, ref cnd) = &*mutcond;
+                    let mut guard = mtx.lock().unwrap();
+                    let listing = &mut guard.1;
+                    listing.set_timestamp();
+                    debug!(
+                        "Channel list request...done. {} channels received",
+                        &listing.len()
+                    );
+                    /* listing made available from now */
+                    guard.0 = true;
+                    cnd.notify_all();
+                }
+                Command::Response(rpl_type, v) if *rpl_type == Response::RPL_ISUPPORT => {
+                    if let Some(mode) = bot_mode(v) {
+                        let nick = client.current_nickname().to_string();
+                        client.send(Command::UserMODE(
+                            nick,
+                            vec![Mode::Plus(UserMode::Unknown(mode), None)],
+                        ))?;
+                    }
                 }
-                send_list_command(&client);
+                Command::Response(rpl_type, _) if *rpl_type == Response::RPL_WELCOME => {
+                    let caps = ctx.caps.list();
+                    if !caps.is_empty() {
+                        info!("Capabilities enabled on {}: {}", server, caps.join(", "));
+                    }
+                    if let Some(Prefix::ServerName(name)) = &message.prefix {
+                        server_name = Some(name.to_string());
+                    }
+                    if nickserv {
+                        /* some networks only allow LIST to identified clients */
+                        login_deadline = Some(Instant::now() + LOGIN_TIMEOUT);
+                    } else {
+                        send_list_command(&client);
+                    }
+                }
+                Command::Response(rpl_type, _)
+                    if *rpl_type == Response::RPL_LOGGEDIN && login_deadline.is_some() =>
+                {
+                    login_deadline = None;
+                    send_list_command(&client);
+                }
+                _ => (),
             }
-            _ => (),
         }
+        Ok(())
     }
+    .await;
 
-    let _ = privmsg_thread.join();
+    /* release a request possibly waiting for a listing that will never come */
+    {
+        let (mtx, cnd) = &*mutcond;
+        mtx.lock().unwrap().0 = true;
+        cnd.notify_all();
+    }
+    drop(ms);
+    let _ = tokio::task::spawn_blocking(move || privmsg_thread.join()).await;
+    info!("Disconnected from {}", server);
+    disconnection(quit_deadline.is_some(), served)
+}
 
-    Ok(())
+/// Runs a command received in a private message or prefixed in a channel.
+fn handle_privmsg(ctx: &Context, message: &Message) {
+    if let Command::PRIVMSG(target, msg) = &message.command {
+        let user = match User::from_message(message) {
+            Some(u) => u,
+            None => return,
+        };
+        /* settings may be reloaded meanwhile, work on a snapshot */
+        let settings = ctx.network.settings.read().unwrap().clone();
+        let msg = match command_line(&settings, msg, !target.is_channel_name()) {
+            Some(m) => m,
+            None => return,
+        };
+        privmsg_parse(ctx, &settings, &user, msg);
+    }
+}
+
+fn save_searches(network: &Network) {
+    if let Err(e) = network.save_searches() {
+        error!("Cannot write search statistics: {}", e);
+    }
+}
+
+/// How a connection ended. Once a QUIT was sent, the stop request is consumed
+/// and the connection must not be restarted, even if it then failed.
+fn disconnection(
+    quit_sent: bool,
+    served: irc::error::Result<()>,
+) -> irc::error::Result<Disconnection> {
+    match (quit_sent, served) {
+        (true, Err(e)) => {
+            debug!("Connection error after QUIT: {}", e);
+            Ok(Disconnection::Quit)
+        }
+        (true, Ok(())) => Ok(Disconnection::Quit),
+        (false, Ok(())) => Ok(Disconnection::Lost),
+        (false, Err(e)) => Err(e),
+    }
 }
 
 #[cfg(test)]
@@ -309,6 +858,65 @@ mod tests {
         )
     }
     #[test]
+    fn settings_only_reload() {
+        let config = Config {
+            server: Some("irc.example.org".to_string()),
+            ..Config::default()
+        };
+        let mut options = HashMap::new();
+        options.insert("cache_time".to_string(), "60".to_string());
+        let new_settings = Config {
+            options,
+            ..config.clone()
+        };
+        assert!(same_connection(&config, &new_settings));
+        let mut options = HashMap::new();
+        options.insert("sasl".to_string(), "external".to_string());
+        let new_login = Config {
+            options,
+            ..config.clone()
+        };
+        assert!(!same_connection(&config, &new_login));
+        let new_server = Config {
+            server: Some("irc.example.net".to_string()),
+            ..config.clone()
+        };
+        assert!(!same_connection(&config, &new_server));
+    }
+    #[test]
+    fn quit_then_stream_error() {
+        let error = || Err(irc::error::Error::AsyncChannelClosed);
+        assert!(matches!(
+            disconnection(true, error()),
+            Ok(Disconnection::Quit)
+        ));
+        assert!(matches!(
+            disconnection(true, Ok(())),
+            Ok(Disconnection::Quit)
+        ));
+        assert!(matches!(
+            disconnection(false, Ok(())),
+            Ok(Disconnection::Lost)
+        ));
+        assert!(disconnection(false, error()).is_err());
+    }
+    #[test]
+    fn exponential_backoff() {
+        let base = Duration::from_secs(5);
+        let max = Duration::from_secs(600);
+        assert_eq!(backoff_delay(base, max, 1, 0.999_999).as_secs(), 4);
+        assert_eq!(
+            backoff_delay(base, max, 1, 0.0),
+            Duration::from_millis(2500)
+        );
+        assert_eq!(backoff_delay(base, max, 3, 0.0), Duration::from_secs(10));
+        assert_eq!(backoff_delay(base, max, 8, 0.0), Duration::from_secs(300));
+        assert_eq!(
+            backoff_delay(base, max, 1000, 0.0),
+            Duration::from_secs(300)
+        );
+    }
+    #[test]
     fn no_default_config_file() {
         rename("example_config.toml", "tmp_test.toml").unwrap();
         assert!(get_config_path_from_default().is_err());
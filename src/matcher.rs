@@ -0,0 +1,200 @@
+use glob::{MatchOptions, Pattern};
+use std::ops::Range;
+
+/// Patterns ignore the case of ASCII letters, like the glob crate does.
+pub const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnySequence,
+    /// `[a-z_]`, or `[!...]` when negated
+    Class(bool, Vec<(char, char)>),
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => expected.eq_ignore_ascii_case(&c),
+            Token::AnyChar => true,
+            Token::AnySequence => false,
+            Token::Class(negated, ranges) => {
+                let cases = [c, c.to_ascii_lowercase(), c.to_ascii_uppercase()];
+                let in_class = ranges
+                    .iter()
+                    .any(|(start, end)| cases.iter().any(|c| start <= c && c <= end));
+                in_class != *negated
+            }
+        }
+    }
+}
+
+/// Reads a pattern already checked by `Pattern::new`, with the same syntax.
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '?' => tokens.push(Token::AnyChar),
+            '*' => tokens.push(Token::AnySequence),
+            '[' => {
+                let negated = chars.get(i + 1) == Some(&'!');
+                let start = if negated { i + 2 } else { i + 1 };
+                /* a ']' right after the opening bracket is part of the class */
+                let end = chars
+                    .get(start + 1..)
+                    .and_then(|rest| rest.iter().position(|c| *c == ']'))
+                    .map(|end| start + 1 + end);
+                match end {
+                    Some(end) => {
+                        let mut ranges = Vec::new();
+                        let mut j = start;
+                        while j < end {
+                            if j + 2 < end && chars[j + 1] == '-' {
+                                ranges.push((chars[j], chars[j + 2]));
+                                j += 3;
+                            } else {
+                                ranges.push((chars[j], chars[j]));
+                                j += 1;
+                            }
+                        }
+                        tokens.push(Token::Class(negated, ranges));
+                        i = end;
+                    }
+                    None => tokens.push(Token::Char('[')),
+                }
+            }
+            c => tokens.push(Token::Char(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Byte ranges of `text` matched by the characters, `?` and `[...]` of the
+/// pattern, `None` when it does not match. Wildcards match as few characters
+/// as possible, so `*rust*` highlights the first "rust".
+pub fn match_spans(pattern: &Pattern, text: &str) -> Option<Vec<Range<usize>>> {
+    /* "**" follows path rules, left to the glob crate without highlighting */
+    if pattern.as_str().contains("**") {
+        return if pattern.matches_with(text, MATCH_OPTIONS) {
+            Some(Vec::new())
+        } else {
+            None
+        };
+    }
+    let tokens = tokenize(pattern.as_str());
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let (m, n) = (tokens.len(), chars.len());
+    /* matches[at(i, j)]: tokens[i..] match chars[j..] */
+    let at = |i: usize, j: usize| i * (n + 1) + j;
+    let mut matches = vec![false; (m + 1) * (n + 1)];
+    matches[at(m, n)] = true;
+    for i in (0..m).rev() {
+        for j in (0..=n).rev() {
+            matches[at(i, j)] = match &tokens[i] {
+                Token::AnySequence => matches[at(i + 1, j)] || (j < n && matches[at(i, j + 1)]),
+                token => j < n && token.matches(chars[j].1) && matches[at(i + 1, j + 1)],
+            };
+        }
+    }
+    if !matches[at(0, 0)] {
+        return None;
+    }
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    let mut j = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        if *token == Token::AnySequence {
+            while !matches[at(i + 1, j)] {
+                j += 1;
+            }
+            continue;
+        }
+        let (start, c) = chars[j];
+        let end = start + c.len_utf8();
+        match spans.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => spans.push(start..end),
+        }
+        j += 1;
+    }
+    Some(spans)
+}
+
+/// Puts the spans of `text` in bold.
+pub fn highlight(text: &str, spans: &[Range<usize>]) -> String {
+    let mut highlighted = String::with_capacity(text.len() + 2 * spans.len());
+    let mut last = 0;
+    for span in spans {
+        highlighted.push_str(&text[last..span.start]);
+        highlighted.push('\x02');
+        highlighted.push_str(&text[span.clone()]);
+        highlighted.push('\x02');
+        last = span.end;
+    }
+    highlighted.push_str(&text[last..]);
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(pattern: &str, text: &str) -> Option<Vec<(usize, usize)>> {
+        let pattern = Pattern::new(pattern).unwrap();
+        let spans = match_spans(&pattern, text);
+        /* same result as the glob crate */
+        assert_eq!(
+            spans.is_some(),
+            pattern.matches_with(text, MATCH_OPTIONS),
+            "{} {}",
+            pattern,
+            text
+        );
+        spans.map(|spans| spans.iter().map(|s| (s.start, s.end)).collect())
+    }
+    #[test]
+    fn spans_of_matches() {
+        assert_eq!(spans("*rust*", "#rust-rust"), Some(vec![(1, 5)]));
+        assert_eq!(
+            spans("*foo*bar*", "my foo and bar"),
+            Some(vec![(3, 6), (11, 14)])
+        );
+        assert_eq!(spans("#r?st", "#rust"), Some(vec![(0, 5)]));
+        assert_eq!(spans("*", "#rust"), Some(vec![]));
+        assert_eq!(spans("*[0-9]*", "#c99"), Some(vec![(2, 3)]));
+        assert_eq!(spans("*é*", "#café"), Some(vec![(4, 6)]));
+        assert_eq!(spans("*rust", "#rust-rust"), Some(vec![(6, 10)]));
+        assert_eq!(spans("*rust*", "#RUST"), Some(vec![(1, 5)]));
+        assert_eq!(spans("#[a-c]*", "#Bash"), Some(vec![(0, 2)]));
+        assert_eq!(spans("*rust*", "#go"), None);
+        assert_eq!(spans("#[!a-z]*", "#abc"), None);
+        for (pattern, text) in &[
+            ("[]]*", "]x"),
+            ("[!]]*", "a"),
+            ("*[a-]", "x-"),
+            ("**", "a/b"),
+            ("a/**/b", "a/b"),
+            ("a*b*c", "aXbYc"),
+            ("a*b*c", "aXbY"),
+        ] {
+            spans(pattern, text);
+        }
+    }
+    #[test]
+    fn bold_spans() {
+        assert_eq!(
+            highlight("my foo and bar", &[3..6, 11..14]),
+            "my \x02foo\x02 and \x02bar\x02"
+        );
+        assert_eq!(highlight("#rust", &[]), "#rust");
+    }
+}
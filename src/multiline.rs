@@ -0,0 +1,168 @@
+use crate::settings::ReplyType;
+use irc::client::prelude::*;
+use irc::proto::message::Tag;
+use irc::proto::BatchSubCommand;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// IRCv3 capability allowing several lines to be sent as one message.
+pub const MULTILINE_CAP: &str = "draft/multiline";
+const BATCH_TAG: &str = "batch";
+/* references of the batches opened by the bot, unique per process */
+static NEXT_BATCH: AtomicUsize = AtomicUsize::new(1);
+
+/// Size of the batches the server accepts, from the `draft/multiline` value,
+/// e.g. `max-bytes=4096,max-lines=24`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub max_bytes: usize,
+    pub max_lines: Option<usize>,
+}
+
+impl Limits {
+    /// Reads the capability value, `max-bytes` being mandatory.
+    pub fn parse(value: &str) -> Option<Limits> {
+        let mut max_bytes = None;
+        let mut max_lines = None;
+        for token in value.split(',') {
+            let (key, number) = match token.find('=') {
+                Some(i) => (&token[..i], token[i + 1..].parse::<usize>().ok()),
+                None => (token, None),
+            };
+            match key {
+                "max-bytes" => max_bytes = number,
+                "max-lines" => max_lines = number,
+                _ => (),
+            }
+        }
+        Some(Limits {
+            max_bytes: max_bytes.filter(|n| *n > 0)?,
+            max_lines: max_lines.filter(|n| *n > 0),
+        })
+    }
+}
+
+/// Groups lines into the fewest batches within the limits, keeping their
+/// order. A line longer than `max_bytes` goes alone in its batch.
+pub fn batches(lines: Vec<String>, limits: &Limits) -> Vec<Vec<String>> {
+    let mut batches: Vec<Vec<String>> = Vec::new();
+    let mut bytes = 0;
+    for line in lines {
+        /* lines are joined with a line feed by clients */
+        let size = line.len() + 1;
+        let full = match batches.last() {
+            Some(batch) => {
+                bytes + size > limits.max_bytes
+                    || limits.max_lines.is_some_and(|max| batch.len() >= max)
+            }
+            None => true,
+        };
+        if full {
+            batches.push(Vec::new());
+            bytes = 0;
+        }
+        bytes += size;
+        batches.last_mut().unwrap().push(line);
+    }
+    batches
+}
+
+/// Messages sending `lines` to `target` as a single multiline message.
+pub fn messages(reply_type: &ReplyType, target: &str, lines: &[String]) -> Vec<Message> {
+    let reference = format!("alis{}", NEXT_BATCH.fetch_add(1, Ordering::Relaxed));
+    let mut messages = vec![Message::from(Command::BATCH(
+        format!("+{}", reference),
+        Some(BatchSubCommand::CUSTOM(MULTILINE_CAP.to_string())),
+        Some(vec![target.to_string()]),
+    ))];
+    for line in lines {
+        let command = match reply_type {
+            ReplyType::Privmsg => Command::PRIVMSG(target.to_string(), line.clone()),
+            ReplyType::Notice => Command::NOTICE(target.to_string(), line.clone()),
+        };
+        messages.push(Message {
+            tags: Some(vec![Tag(BATCH_TAG.to_string(), Some(reference.clone()))]),
+            prefix: None,
+            command,
+        });
+    }
+    messages.push(Message::from(Command::BATCH(
+        format!("-{}", reference),
+        None,
+        None,
+    )));
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+    #[test]
+    fn parse_limits() {
+        assert_eq!(
+            Limits::parse("max-bytes=4096,max-lines=24"),
+            Some(Limits {
+                max_bytes: 4096,
+                max_lines: Some(24)
+            })
+        );
+        assert_eq!(
+            Limits::parse("max-bytes=40000"),
+            Some(Limits {
+                max_bytes: 40000,
+                max_lines: None
+            })
+        );
+        assert_eq!(Limits::parse("max-lines=24"), None);
+        assert_eq!(Limits::parse("max-bytes=x"), None);
+    }
+    #[test]
+    fn group_lines() {
+        let limits = Limits {
+            max_bytes: 12,
+            max_lines: Some(3),
+        };
+        assert_eq!(
+            batches(lines(&["aaaa", "bbbb", "cc", "d", "e", "f"]), &limits),
+            vec![
+                lines(&["aaaa", "bbbb"]),
+                lines(&["cc", "d", "e"]),
+                lines(&["f"])
+            ]
+        );
+        assert_eq!(
+            batches(lines(&["a", "much too long line", "b"]), &limits),
+            vec![lines(&["a"]), lines(&["much too long line"]), lines(&["b"])]
+        );
+        assert!(batches(Vec::new(), &limits).is_empty());
+    }
+    #[test]
+    fn batch_messages() {
+        let messages: Vec<String> = messages(
+            &ReplyType::Notice,
+            "user",
+            &lines(&["#rust (120) Rust", "Total: 1"]),
+        )
+        .iter()
+        .map(|message| message.to_string())
+        .collect();
+        assert_eq!(messages.len(), 4);
+        let reference = messages[0]
+            .trim_end()
+            .strip_prefix("BATCH +")
+            .and_then(|rest| rest.strip_suffix(" draft/multiline user"))
+            .unwrap();
+        assert_eq!(
+            messages[1],
+            format!("@batch={} NOTICE user :#rust (120) Rust\r\n", reference)
+        );
+        assert_eq!(
+            messages[2],
+            format!("@batch={} NOTICE user :Total: 1\r\n", reference)
+        );
+        assert_eq!(messages[3], format!("BATCH -{}\r\n", reference));
+    }
+}
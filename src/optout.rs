@@ -0,0 +1,157 @@
+use crate::i18n::{self, Lang};
+use crate::{listfile, send_reply, BotCommand, Context, Outcome, Permission, Settings, User};
+use failure::Error;
+use log::info;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+pub const OPTOUT_COMMAND: &str = "optout";
+pub const OPTIN_COMMAND: &str = "optin";
+
+/// Channels delisted at the request of their operators.
+#[derive(Debug, Default)]
+pub struct OptOutList {
+    /* lowercase channel names */
+    channels: BTreeSet<String>,
+}
+
+impl OptOutList {
+    pub fn new() -> OptOutList {
+        OptOutList {
+            channels: BTreeSet::new(),
+        }
+    }
+    /// Reads channels from a file, one per line. A missing file is an empty list.
+    pub fn load(path: &Path) -> Result<OptOutList, Error> {
+        let mut list = OptOutList::new();
+        for channel in listfile::read_lines(path)? {
+            list.add(&channel);
+        }
+        Ok(list)
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        listfile::write_lines(path, "channels delisted by their operators", &self.channels)
+    }
+    /// Returns false if the channel was already delisted.
+    pub fn add(&mut self, channel: &str) -> bool {
+        self.channels.insert(channel.to_lowercase())
+    }
+    /// Returns false if the channel was not delisted.
+    pub fn remove(&mut self, channel: &str) -> bool {
+        self.channels.remove(&channel.to_lowercase())
+    }
+    pub fn contains(&self, channel: &str) -> bool {
+        self.channels.contains(&channel.to_lowercase())
+    }
+}
+
+/// `optout #chan` and `optin #chan`, allowed to channel operators and owners.
+pub struct OptOutCommand {
+    optin: bool,
+}
+
+impl OptOutCommand {
+    pub fn optout() -> OptOutCommand {
+        OptOutCommand { optin: false }
+    }
+    pub fn optin() -> OptOutCommand {
+        OptOutCommand { optin: true }
+    }
+}
+
+impl BotCommand for OptOutCommand {
+    fn name(&self) -> &str {
+        if self.optin {
+            OPTIN_COMMAND
+        } else {
+            OPTOUT_COMMAND
+        }
+    }
+    fn syntax(&self) -> &str {
+        if self.optin {
+            "optin <#channel>"
+        } else {
+            "optout <#channel>"
+        }
+    }
+    fn summary(&self) -> &str {
+        if self.optin {
+            "shows your channel in results again"
+        } else {
+            "hides your channel from results"
+        }
+    }
+    fn help(&self, _lang: Lang) -> Vec<String> {
+        vec![
+            "Operator status is checked with a WHOIS, the channel must be visible in it."
+                .to_string(),
+        ]
+    }
+    fn permission(&self) -> Permission {
+        Permission::ChannelOperator
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        let lang = ctx.lang(settings, user);
+        let reply = match run(ctx, user, args, lang) {
+            Ok(reply) => reply,
+            Err(e) => i18n::error_message(lang, &e),
+        };
+        send_reply(ctx, settings, &user.nick, reply);
+        Outcome::default()
+    }
+}
+
+fn run(ctx: &Context, user: &User, args: &[&str], lang: Lang) -> Result<String, Error> {
+    let command = args[0].to_lowercase();
+    let channel = match args {
+        [_, channel] if channel.starts_with('#') => channel,
+        _ => return Err(localized!("optout.usage", command)),
+    };
+    if !ctx.is_channel_op(user, channel) {
+        return Err(localized!("chan.not_op", channel));
+    }
+    let changed = {
+        let mut optouts = ctx.network.optouts.write().unwrap();
+        if command == OPTOUT_COMMAND {
+            optouts.add(channel)
+        } else {
+            optouts.remove(channel)
+        }
+    };
+    match (command.as_str(), changed) {
+        (OPTOUT_COMMAND, true) => {
+            info!("{} delisted {}", user.hostmask, channel);
+            ctx.network.save_optouts()?;
+            Ok(tr!(lang, "optout.done", channel))
+        }
+        (OPTOUT_COMMAND, false) => Ok(tr!(lang, "optout.already", channel)),
+        (_, true) => {
+            info!("{} listed {} again", user.hostmask, channel);
+            ctx.network.save_optouts()?;
+            Ok(tr!(lang, "optin.done", channel))
+        }
+        (_, false) => Ok(tr!(lang, "optin.not_delisted", channel)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delist_channels() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("optout.txt");
+        let mut optouts = OptOutList::new();
+        assert!(optouts.add("#Secret"));
+        assert!(!optouts.add("#secret"));
+        assert!(optouts.add("#other"));
+        assert!(optouts.contains("#SECRET"));
+        optouts.save(&path).unwrap();
+        let mut optouts = OptOutList::load(&path).unwrap();
+        assert!(optouts.contains("#secret"));
+        assert!(optouts.remove("#other"));
+        assert!(!optouts.remove("#other"));
+        assert!(!optouts.contains("#other"));
+    }
+}
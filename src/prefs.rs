@@ -0,0 +1,453 @@
+use crate::i18n::{self, Lang};
+use crate::listfile;
+use crate::settings::{parse_columns, Column, ReplyType, Sort};
+use crate::{send_reply, BotCommand, Context, Outcome, Settings, User};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const SET_COMMAND: &str = "set";
+pub const SETTINGS_COMMAND: &str = "settings";
+/// User preference changed with `set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Setting {
+    Lang,
+    Reply,
+    Limit,
+    Sort,
+    Columns,
+    Colors,
+    Highlight,
+}
+
+const SETTINGS: [Setting; 7] = [
+    Setting::Lang,
+    Setting::Reply,
+    Setting::Limit,
+    Setting::Sort,
+    Setting::Columns,
+    Setting::Colors,
+    Setting::Highlight,
+];
+
+impl Setting {
+    fn name(self) -> &'static str {
+        match self {
+            Setting::Lang => "lang",
+            Setting::Reply => "reply",
+            Setting::Limit => "limit",
+            Setting::Sort => "sort",
+            Setting::Columns => "columns",
+            Setting::Colors => "colors",
+            Setting::Highlight => "highlight",
+        }
+    }
+}
+
+fn setting_names() -> String {
+    SETTINGS
+        .iter()
+        .map(|setting| setting.name())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+/// Preferences of a user, unset ones following the network settings.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserPrefs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<Lang>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_type: Option<ReplyType>,
+    /// channels shown without --limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Sort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<Column>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<bool>,
+}
+
+impl UserPrefs {
+    fn is_empty(&self) -> bool {
+        *self == UserPrefs::default()
+    }
+    /// Replaces the network settings the user changed.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(lang) = self.lang {
+            settings.lang = lang;
+        }
+        if let Some(reply_type) = self.reply_type {
+            settings.reply_type = reply_type;
+        }
+        if let Some(limit) = self.limit {
+            settings.default_limit = Some(limit);
+        }
+        if let Some(sort) = self.sort {
+            settings.default_sort = Some(sort);
+        }
+        if let Some(columns) = &self.columns {
+            settings.columns = columns.clone();
+        }
+        if let Some(colors) = self.colors {
+            settings.colors = colors;
+        }
+        if let Some(highlight) = self.highlight {
+            settings.highlight = highlight;
+        }
+    }
+}
+
+/// Preferences by lowercase services account, or by nickname for users who
+/// are not identified.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrefsStore {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    accounts: BTreeMap<String, UserPrefs>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    nicks: BTreeMap<String, UserPrefs>,
+}
+
+impl PrefsStore {
+    pub fn new() -> PrefsStore {
+        PrefsStore::default()
+    }
+    /// Reads a TOML file with `accounts` and `nicks` tables. A missing file is
+    /// an empty store.
+    pub fn load(path: &Path) -> Result<PrefsStore, Error> {
+        let content = match listfile::read(path)? {
+            Some(content) => content,
+            None => return Ok(PrefsStore::new()),
+        };
+        toml::from_str(&content).map_err(|e| format_err!("{}: {}", path.display(), e))
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = toml::to_string(self)?;
+        listfile::write_atomic(path, &content)
+    }
+    fn users(&self, user: &User) -> (&BTreeMap<String, UserPrefs>, String) {
+        match &user.account {
+            Some(account) => (&self.accounts, account.to_lowercase()),
+            None => (&self.nicks, user.nick.to_lowercase()),
+        }
+    }
+    pub fn get(&self, user: &User) -> Option<&UserPrefs> {
+        let (users, key) = self.users(user);
+        users.get(&key)
+    }
+    /// Changes the preferences of a user, dropping them once empty.
+    pub fn update<F: FnOnce(&mut UserPrefs)>(&mut self, user: &User, f: F) {
+        let (users, key) = match &user.account {
+            Some(account) => (&mut self.accounts, account.to_lowercase()),
+            None => (&mut self.nicks, user.nick.to_lowercase()),
+        };
+        let prefs = users.entry(key.clone()).or_default();
+        f(prefs);
+        if prefs.is_empty() {
+            users.remove(&key);
+        }
+    }
+}
+
+/// `set <setting> [value]`, a missing value restoring the network default.
+pub struct SetCommand;
+
+impl BotCommand for SetCommand {
+    fn name(&self) -> &str {
+        SET_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "set <setting> [value]"
+    }
+    fn summary(&self) -> &str {
+        "changes your settings, e.g. set lang fr"
+    }
+    fn help(&self, _lang: Lang) -> Vec<String> {
+        vec![
+            format!(
+                "lang: language of the replies, {}",
+                i18n::LANGS
+                    .iter()
+                    .map(|lang| lang.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" or ")
+            ),
+            "reply: type of the replies, privmsg or notice".to_string(),
+            "limit: channels shown without --limit, no value for the network one".to_string(),
+            "sort: order of the results without --sort, users (most first) or name".to_string(),
+            "columns: channel details shown, e.g. name,users".to_string(),
+            "colors: on or off, keeps or removes bold and colors".to_string(),
+            "highlight: on or off, shows in bold what your patterns matched".to_string(),
+        ]
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        let reply = match set(ctx, user, args) {
+            /* confirmed with the new settings, e.g. in the new language */
+            Ok((setting, value)) => {
+                let settings = ctx.user_settings(settings, user);
+                let reply = match (setting, value) {
+                    (Setting::Lang, Some(_)) => tr!(settings.lang, "set.lang"),
+                    (Setting::Lang, None) => tr!(settings.lang, "set.lang_default"),
+                    (setting, Some(value)) => {
+                        tr!(settings.lang, "set.done", setting.name(), value)
+                    }
+                    (setting, None) => tr!(settings.lang, "set.reset", setting.name()),
+                };
+                send_reply(ctx, &settings, &user.nick, reply);
+                return Outcome::default();
+            }
+            Err(e) => i18n::error_message(ctx.lang(settings, user), &e),
+        };
+        send_reply(ctx, settings, &user.nick, reply);
+        Outcome::default()
+    }
+}
+
+/// Changes a setting, returning its name and new value, `None` when reset.
+fn set(ctx: &Context, user: &User, args: &[&str]) -> Result<(Setting, Option<String>), Error> {
+    let setting = match args.get(1) {
+        Some(setting) => setting.to_lowercase(),
+        None => return Err(localized!("set.usage", setting_names())),
+    };
+    let setting = match SETTINGS.iter().find(|s| s.name() == setting) {
+        Some(setting) => *setting,
+        None => return Err(localized!("set.unknown", setting, setting_names())),
+    };
+    /* columns may be given with spaces, e.g. "name, users" */
+    let value = match args.get(2..) {
+        Some(values) if !values.is_empty() => Some(values.join(" ")),
+        _ => None,
+    };
+    let mut prefs = UserPrefs::default();
+    let shown = match (setting, value.as_deref()) {
+        (_, None) => None,
+        (Setting::Lang, Some(v)) => {
+            prefs.lang = Some(v.parse::<Lang>()?);
+            prefs.lang.map(|v| v.to_string())
+        }
+        (Setting::Reply, Some(v)) => {
+            let reply_type = v
+                .parse::<ReplyType>()
+                .map_err(|_| localized!("value.invalid", v, "privmsg, notice"))?;
+            prefs.reply_type = Some(reply_type);
+            Some(reply_type.to_string())
+        }
+        (Setting::Limit, Some(v)) => {
+            let limit = match v.parse::<u32>() {
+                Ok(limit) if limit > 0 => limit,
+                _ => return Err(localized!("value.invalid", v, "1, 2, 3...")),
+            };
+            prefs.limit = Some(limit);
+            Some(limit.to_string())
+        }
+        (Setting::Sort, Some(v)) => {
+            prefs.sort = Some(v.parse::<Sort>()?);
+            prefs.sort.map(|v| v.to_string())
+        }
+        (Setting::Columns, Some(v)) => {
+            let columns = parse_columns(v)?;
+            let shown = join(&columns);
+            prefs.columns = Some(columns);
+            Some(shown)
+        }
+        (Setting::Colors, Some(v)) => {
+            let colors = parse_switch(v)?;
+            prefs.colors = Some(colors);
+            Some(switch(colors))
+        }
+        (Setting::Highlight, Some(v)) => {
+            let highlight = parse_switch(v)?;
+            prefs.highlight = Some(highlight);
+            Some(switch(highlight))
+        }
+    };
+    ctx.network
+        .prefs
+        .write()
+        .unwrap()
+        .update(user, |p| match setting {
+            Setting::Lang => p.lang = prefs.lang,
+            Setting::Reply => p.reply_type = prefs.reply_type,
+            Setting::Limit => p.limit = prefs.limit,
+            Setting::Sort => p.sort = prefs.sort,
+            Setting::Columns => p.columns = prefs.columns.take(),
+            Setting::Colors => p.colors = prefs.colors,
+            Setting::Highlight => p.highlight = prefs.highlight,
+        });
+    ctx.network.save_prefs()?;
+    Ok((setting, shown))
+}
+
+fn parse_switch(value: &str) -> Result<bool, Error> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        _ => Err(localized!("value.invalid", value, "on, off")),
+    }
+}
+
+fn switch(on: bool) -> String {
+    if on { "on" } else { "off" }.to_string()
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// `settings`, the effective settings of the user.
+pub struct SettingsCommand;
+
+impl BotCommand for SettingsCommand {
+    fn name(&self) -> &str {
+        SETTINGS_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "settings"
+    }
+    fn summary(&self) -> &str {
+        "shows your settings"
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, _: &[&str]) -> Outcome {
+        let prefs = ctx.network.prefs.read().unwrap().get(user).cloned();
+        let prefs = prefs.unwrap_or_default();
+        let effective = ctx.user_settings(settings, user);
+        let lang = effective.lang;
+        let values = [
+            (
+                Setting::Lang.name(),
+                prefs.lang.is_some(),
+                effective.lang.to_string(),
+            ),
+            (
+                Setting::Reply.name(),
+                prefs.reply_type.is_some(),
+                effective.reply_type.to_string(),
+            ),
+            (
+                Setting::Limit.name(),
+                prefs.limit.is_some(),
+                effective
+                    .default_limit
+                    .map_or("-".to_string(), |l| l.to_string()),
+            ),
+            (
+                Setting::Sort.name(),
+                prefs.sort.is_some(),
+                effective
+                    .default_sort
+                    .map_or("-".to_string(), |s| s.to_string()),
+            ),
+            (
+                Setting::Columns.name(),
+                prefs.columns.is_some(),
+                join(&effective.columns),
+            ),
+            (
+                Setting::Colors.name(),
+                prefs.colors.is_some(),
+                switch(effective.colors),
+            ),
+            (
+                Setting::Highlight.name(),
+                prefs.highlight.is_some(),
+                switch(effective.highlight),
+            ),
+        ];
+        let values: Vec<String> = values
+            .iter()
+            .map(|(setting, chosen, value)| {
+                if *chosen {
+                    format!("{} {}", setting, value)
+                } else {
+                    format!("{} {} {}", setting, value, tr!(lang, "settings.default"))
+                }
+            })
+            .collect();
+        let reply = tr!(lang, "settings.show", values.join(", "));
+        send_reply(ctx, &effective, &user.nick, reply);
+        Outcome::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn user(nick: &str, account: Option<&str>) -> User {
+        User {
+            nick: nick.to_string(),
+            hostmask: format!("{}!u@host", nick),
+            host: "host".to_string(),
+            account: account.map(|a| a.to_string()),
+        }
+    }
+    #[test]
+    fn accounts_and_nicks() {
+        let mut prefs = PrefsStore::new();
+        let identified = user("Jean", Some("JeanD"));
+        prefs.update(&identified, |p| p.lang = Some(Lang::Fr));
+        assert_eq!(prefs.get(&identified).unwrap().lang, Some(Lang::Fr));
+        /* same account under another nick */
+        assert!(prefs.get(&user("jean_", Some("jeand"))).is_some());
+        /* a nickname does not give the preferences of an account */
+        assert_eq!(prefs.get(&user("jeand", None)), None);
+        prefs.update(&user("Guest", None), |p| p.lang = Some(Lang::En));
+        assert_eq!(prefs.nicks["guest"].lang, Some(Lang::En));
+        prefs.update(&identified, |p| p.lang = None);
+        assert_eq!(prefs.get(&identified), None);
+    }
+    #[test]
+    fn apply_prefs() {
+        let mut settings = Settings::default();
+        let prefs = UserPrefs {
+            reply_type: Some(ReplyType::Notice),
+            limit: Some(5),
+            sort: Some(Sort::Name),
+            colors: Some(false),
+            ..UserPrefs::default()
+        };
+        prefs.apply(&mut settings);
+        assert_eq!(settings.reply_type, ReplyType::Notice);
+        assert_eq!(settings.default_limit, Some(5));
+        assert_eq!(settings.default_sort, Some(Sort::Name));
+        assert_eq!(settings.columns, Settings::default().columns);
+        assert!(!settings.colors);
+        assert_eq!(settings.lang, Lang::En);
+        assert!(parse_switch("On").unwrap());
+        assert!(!parse_switch("no").unwrap());
+        assert!(parse_switch("maybe").is_err());
+    }
+    #[test]
+    fn load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prefs.toml");
+        assert_eq!(PrefsStore::load(&path).unwrap(), PrefsStore::new());
+        let mut prefs = PrefsStore::new();
+        prefs.update(&user("jean", Some("jeand")), |p| p.lang = Some(Lang::Fr));
+        prefs.update(&user("guest", None), |p| p.lang = Some(Lang::En));
+        prefs.save(&path).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("[accounts.jeand]\nlang = \"fr\""));
+        assert_eq!(PrefsStore::load(&path).unwrap(), prefs);
+        prefs.update(&user("guest", None), |p| {
+            p.columns = Some(vec![Column::Name, Column::Users]);
+            p.colors = Some(false);
+        });
+        prefs.save(&path).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("columns = [\"name\", \"users\"]"));
+        assert_eq!(PrefsStore::load(&path).unwrap(), prefs);
+        fs::write(&path, "[accounts.x]\nlang = \"de\"\n").unwrap();
+        assert!(PrefsStore::load(&path).is_err());
+    }
+}
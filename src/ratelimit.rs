@@ -0,0 +1,194 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Commands allowed per user in a sliding window, and abuse thresholds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimits {
+    /// commands allowed per user during `period`
+    pub commands: usize,
+    pub period: Duration,
+    /// commands rejected during `period` before the user is ignored
+    pub strikes: usize,
+    /// how long abusers are ignored
+    pub ignore_time: Duration,
+    /// also limits the commands of all users sharing a host
+    pub per_host: bool,
+}
+
+/// Why a command was rejected.
+#[derive(Debug, PartialEq)]
+pub enum Limited {
+    /// the user may retry after this delay
+    SlowDown(Duration),
+    /// the user exceeded the abuse threshold and should be ignored
+    Abuse,
+}
+
+/// Per user, and optionally per host, command history, users being identified
+/// by their services account when known, by their nickname otherwise.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    commands: HashMap<String, VecDeque<Instant>>,
+    rejected: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            commands: HashMap::new(),
+            rejected: HashMap::new(),
+        }
+    }
+    /// Records a command from `nick`, or its `account`, on `host`, unless the
+    /// user, or the host when limited, sent too many commands during the last
+    /// period. Abuse is counted per user.
+    pub fn check(
+        &mut self,
+        nick: &str,
+        account: Option<&str>,
+        host: &str,
+        limits: &RateLimits,
+        now: Instant,
+    ) -> Result<(), Limited> {
+        /* an account keeps its history across nickname changes */
+        let user = match account {
+            Some(account) => format!("account:{}", account.to_lowercase()),
+            None => format!("nick:{}", nick.to_lowercase()),
+        };
+        let mut keys = vec![user];
+        if limits.per_host {
+            keys.push(format!("host:{}", host.to_lowercase()));
+        }
+        self.expire(limits.period, now);
+
+        let retry = keys
+            .iter()
+            .filter_map(|key| self.commands.get(key))
+            .filter(|history| history.len() >= limits.commands)
+            .filter_map(|history| history.front())
+            .map(|oldest| limits.period - now.duration_since(*oldest))
+            .max();
+        match retry {
+            None => {
+                for key in keys.iter() {
+                    self.commands.entry(key.clone()).or_default().push_back(now);
+                }
+                Ok(())
+            }
+            Some(retry) => {
+                let rejected = self.rejected.entry(keys[0].clone()).or_default();
+                rejected.push_back(now);
+                if rejected.len() >= limits.strikes {
+                    self.rejected.remove(&keys[0]);
+                    Err(Limited::Abuse)
+                } else {
+                    Err(Limited::SlowDown(retry))
+                }
+            }
+        }
+    }
+    fn expire(&mut self, period: Duration, now: Instant) {
+        for histories in [&mut self.commands, &mut self.rejected].iter_mut() {
+            for history in histories.values_mut() {
+                while let Some(oldest) = history.front() {
+                    if now.duration_since(*oldest) < period {
+                        break;
+                    }
+                    history.pop_front();
+                }
+            }
+            histories.retain(|_, history| !history.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RateLimits {
+        RateLimits {
+            commands: 2,
+            period: Duration::from_secs(60),
+            strikes: 2,
+            ignore_time: Duration::from_secs(600),
+            per_host: false,
+        }
+    }
+    #[test]
+    fn slow_down() {
+        let mut limiter = RateLimiter::new();
+        let start = Instant::now();
+        assert!(limiter
+            .check("nick", None, "host", &limits(), start)
+            .is_ok());
+        let later = start + Duration::from_secs(10);
+        assert!(limiter
+            .check("nick", None, "host", &limits(), later)
+            .is_ok());
+        assert_eq!(
+            limiter.check("nick", None, "host", &limits(), later),
+            Err(Limited::SlowDown(Duration::from_secs(50)))
+        );
+        // other users are not affected
+        assert!(limiter
+            .check("other", None, "elsewhere", &limits(), later)
+            .is_ok());
+        // window slides
+        let much_later = start + Duration::from_secs(61);
+        assert!(limiter
+            .check("nick", None, "host", &limits(), much_later)
+            .is_ok());
+    }
+    #[test]
+    fn limited_by_host() {
+        let mut limiter = RateLimiter::new();
+        let now = Instant::now();
+        /* users sharing a host are not limited together by default */
+        for nick in &["nick1", "nick2", "nick3"] {
+            assert!(limiter.check(nick, None, "host", &limits(), now).is_ok());
+        }
+        let limits = RateLimits {
+            per_host: true,
+            ..limits()
+        };
+        let mut limiter = RateLimiter::new();
+        assert!(limiter.check("nick1", None, "host", &limits, now).is_ok());
+        assert!(limiter.check("nick2", None, "host", &limits, now).is_ok());
+        assert!(limiter.check("nick3", None, "host", &limits, now).is_err());
+    }
+    #[test]
+    fn limited_by_account() {
+        let mut limiter = RateLimiter::new();
+        let now = Instant::now();
+        let account = Some("jeand");
+        assert!(limiter
+            .check("nick1", account, "host1", &limits(), now)
+            .is_ok());
+        assert!(limiter
+            .check("nick2", account, "host2", &limits(), now)
+            .is_ok());
+        assert!(limiter
+            .check("nick3", account, "host3", &limits(), now)
+            .is_err());
+        /* the nickname of an identified user is free for others */
+        assert!(limiter
+            .check("nick1", None, "host4", &limits(), now)
+            .is_ok());
+    }
+    #[test]
+    fn abuse() {
+        let mut limiter = RateLimiter::new();
+        let now = Instant::now();
+        assert!(limiter.check("nick", None, "host", &limits(), now).is_ok());
+        assert!(limiter.check("nick", None, "host", &limits(), now).is_ok());
+        assert!(matches!(
+            limiter.check("nick", None, "host", &limits(), now),
+            Err(Limited::SlowDown(_))
+        ));
+        assert_eq!(
+            limiter.check("nick", None, "host", &limits(), now),
+            Err(Limited::Abuse)
+        );
+    }
+}
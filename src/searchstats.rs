@@ -0,0 +1,302 @@
+use crate::i18n::Lang;
+use crate::listfile;
+use chrono::{naive, Duration, NaiveDate};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/* distinct keys counted per day and category, the least used are dropped beyond */
+const MAX_KEYS: usize = 1000;
+
+/// Searches handled during one day.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DayStats {
+    pub searches: u32,
+    pub empty_searches: u32,
+    /// channel name patterns
+    pub patterns: HashMap<String, u32>,
+    pub topics: HashMap<String, u32>,
+    /// channels sent in results
+    pub channels: HashMap<String, u32>,
+    /// channel name patterns of searches without results
+    pub empty_patterns: HashMap<String, u32>,
+}
+
+/// Search counters aggregated by day.
+#[derive(Debug, Default, PartialEq)]
+pub struct SearchStats {
+    days: BTreeMap<NaiveDate, DayStats>,
+    /* not saved since the last change */
+    changed: bool,
+}
+
+/// Totals over a period, counters sorted by decreasing count.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub searches: u32,
+    pub empty_searches: u32,
+    pub patterns: Vec<(String, u32)>,
+    pub topics: Vec<(String, u32)>,
+    pub channels: Vec<(String, u32)>,
+    pub empty_patterns: Vec<(String, u32)>,
+}
+
+fn increment(counts: &mut HashMap<String, u32>, key: &str) {
+    if !counts.contains_key(key) && counts.len() >= MAX_KEYS {
+        let least = counts
+            .iter()
+            .min_by_key(|(_, count)| **count)
+            .map(|(key, _)| key.clone());
+        if let Some(least) = least {
+            counts.remove(&least);
+        }
+    }
+    *counts.entry(key.to_string()).or_insert(0) += 1;
+}
+
+fn merge(total: &mut HashMap<String, u32>, counts: &HashMap<String, u32>) {
+    for (key, count) in counts {
+        *total.entry(key.clone()).or_insert(0) += count;
+    }
+}
+
+fn top(counts: HashMap<String, u32>) -> Vec<(String, u32)> {
+    let mut top: Vec<(String, u32)> = counts.into_iter().collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top
+}
+
+impl SearchStats {
+    pub fn new() -> SearchStats {
+        SearchStats {
+            days: BTreeMap::new(),
+            changed: false,
+        }
+    }
+    /// Reads counters saved as JSON. A missing file is empty statistics.
+    pub fn load(path: &Path) -> Result<SearchStats, Error> {
+        let content = match listfile::read(path)? {
+            Some(content) => content,
+            None => return Ok(SearchStats::new()),
+        };
+        let days =
+            serde_json::from_str(&content).map_err(|e| format_err!("{}: {}", path.display(), e))?;
+        Ok(SearchStats {
+            days,
+            changed: false,
+        })
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = serde_json::to_string(&self.days)?;
+        listfile::write_atomic(path, &content)
+    }
+    /// Saves the statistics if they changed since they were read or saved.
+    pub fn save_changes(&mut self, path: &Path) -> Result<(), Error> {
+        if self.changed {
+            self.save(path)?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+    /// Counts a search made on `date`, returning `channels`.
+    pub fn record(
+        &mut self,
+        date: NaiveDate,
+        pattern: &str,
+        topic: Option<&str>,
+        results: usize,
+        channels: &[String],
+    ) {
+        self.changed = true;
+        let day = self.days.entry(date).or_default();
+        day.searches += 1;
+        increment(&mut day.patterns, pattern);
+        if let Some(topic) = topic {
+            increment(&mut day.topics, topic);
+        }
+        for channel in channels {
+            increment(&mut day.channels, &channel.to_lowercase());
+        }
+        if results == 0 {
+            day.empty_searches += 1;
+            increment(&mut day.empty_patterns, pattern);
+        }
+    }
+    /// Drops days older than `days` before `today`.
+    pub fn prune(&mut self, today: NaiveDate, days: u32) {
+        if let Some(oldest) = today.checked_sub_signed(Duration::days(i64::from(days))) {
+            let kept = self.days.split_off(&oldest);
+            /* the days before `oldest` were left in `self.days` */
+            self.changed |= !self.days.is_empty();
+            self.days = kept;
+        }
+    }
+    /// Totals of the last `days` days, today included.
+    pub fn summary(&self, today: NaiveDate, days: u32) -> Summary {
+        /* a period reaching before the first representable date covers every day */
+        let since = today
+            .checked_sub_signed(Duration::days(i64::from(days.max(1)) - 1))
+            .unwrap_or(naive::MIN_DATE);
+        let mut total = DayStats::default();
+        for (_, day) in self.days.range(since..) {
+            total.searches += day.searches;
+            total.empty_searches += day.empty_searches;
+            merge(&mut total.patterns, &day.patterns);
+            merge(&mut total.topics, &day.topics);
+            merge(&mut total.channels, &day.channels);
+            merge(&mut total.empty_patterns, &day.empty_patterns);
+        }
+        Summary {
+            searches: total.searches,
+            empty_searches: total.empty_searches,
+            patterns: top(total.patterns),
+            topics: top(total.topics),
+            channels: top(total.channels),
+            empty_patterns: top(total.empty_patterns),
+        }
+    }
+}
+
+/// Parses a period in days, `7d` or `2w`, a bare number being days.
+pub fn parse_period(s: &str) -> Result<u32, Error> {
+    let s = s.to_lowercase();
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, 'd')) => (&s[..i], 1),
+        Some((i, 'w')) => (&s[..i], 7),
+        _ => (s.as_str(), 1),
+    };
+    match number.parse::<u32>() {
+        Ok(n) if n > 0 => n
+            .checked_mul(unit)
+            .ok_or_else(|| localized!("searches.too_long", s)),
+        _ => Err(localized!("searches.invalid", s)),
+    }
+}
+
+/// Formats the first `count` counters, e.g. `*rust* (12), *go* (3)`.
+pub fn format_top(counts: &[(String, u32)], count: usize, lang: Lang) -> String {
+    if counts.is_empty() {
+        return tr!(lang, "popular.none");
+    }
+    counts
+        .iter()
+        .take(count)
+        .map(|(key, n)| format!("{} ({})", key, n))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/* counters shown in reports */
+const REPORT_TOP: usize = 5;
+
+/// Report lines of the last `days` days, `staff` adding searches without results.
+pub fn report(summary: &Summary, days: u32, staff: bool, lang: Lang) -> Vec<String> {
+    let mut lines = vec![tr!(
+        lang,
+        "popular.searches",
+        summary.searches,
+        days,
+        summary.empty_searches
+    )];
+    lines.push(tr!(
+        lang,
+        "popular.patterns",
+        format_top(&summary.patterns, REPORT_TOP, lang)
+    ));
+    lines.push(tr!(
+        lang,
+        "popular.topics",
+        format_top(&summary.topics, REPORT_TOP, lang)
+    ));
+    lines.push(tr!(
+        lang,
+        "popular.channels",
+        format_top(&summary.channels, REPORT_TOP, lang)
+    ));
+    if staff {
+        lines.push(tr!(
+            lang,
+            "popular.empty",
+            format_top(&summary.empty_patterns, REPORT_TOP, lang)
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2021, 1, day)
+    }
+    #[test]
+    fn aggregate_days() {
+        let mut stats = SearchStats::new();
+        let rust = vec!["#rust".to_string(), "#Rust-Beginners".to_string()];
+        stats.record(date(1), "*rust*", None, 2, &rust);
+        stats.record(date(9), "*rust*", Some("*help*"), 1, &rust[..1]);
+        stats.record(date(10), "*zig*", None, 0, &[]);
+        stats.record(date(10), "*rust*", None, 2, &rust);
+
+        let week = stats.summary(date(10), 7);
+        assert_eq!(week.searches, 3);
+        assert_eq!(week.empty_searches, 1);
+        assert_eq!(
+            week.patterns,
+            vec![("*rust*".to_string(), 2), ("*zig*".to_string(), 1)]
+        );
+        assert_eq!(week.topics, vec![("*help*".to_string(), 1)]);
+        assert_eq!(
+            format_top(&week.channels, 5, Lang::En),
+            "#rust (2), #rust-beginners (1)"
+        );
+        assert_eq!(format_top(&week.empty_patterns, 5, Lang::En), "*zig* (1)");
+        assert_eq!(stats.summary(date(10), 10).searches, 4);
+        assert_eq!(stats.summary(date(10), 1).searches, 2);
+
+        let lines = report(&week, 7, false, Lang::En);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "Channel patterns: *rust* (2), *zig* (1)");
+        assert_eq!(
+            report(&week, 7, true, Lang::En)[4],
+            "Searches without results: *zig* (1)"
+        );
+
+        stats.prune(date(10), 5);
+        assert_eq!(stats.summary(date(10), 30).searches, 3);
+        /* periods longer than the calendar */
+        assert_eq!(stats.summary(date(10), u32::MAX).searches, 3);
+        stats.prune(date(10), u32::MAX);
+        assert_eq!(stats.summary(date(10), 30).searches, 3);
+    }
+    #[test]
+    fn load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("searches.json");
+        assert_eq!(SearchStats::load(&path).unwrap(), SearchStats::new());
+        let mut stats = SearchStats::new();
+        stats.save_changes(&path).unwrap();
+        assert!(!path.exists());
+        stats.record(date(1), "*rust*", None, 0, &[]);
+        stats.save_changes(&path).unwrap();
+        assert_eq!(SearchStats::load(&path).unwrap(), stats);
+        stats.prune(date(1), 30);
+        assert!(!stats.changed);
+        stats.prune(date(31), 29);
+        assert!(stats.changed);
+    }
+    #[test]
+    fn periods() {
+        assert_eq!(parse_period("7d").unwrap(), 7);
+        assert_eq!(parse_period("2W").unwrap(), 14);
+        assert_eq!(parse_period("30").unwrap(), 30);
+        assert!(parse_period("0d").is_err());
+        assert!(parse_period("week").is_err());
+        assert_eq!(
+            parse_period("1000000000w").unwrap_err().to_string(),
+            "Period '1000000000w' is too long"
+        );
+    }
+}
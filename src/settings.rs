@@ -0,0 +1,780 @@
+use crate::i18n::Lang;
+use crate::ratelimit::RateLimits;
+use crate::suspicious::{split_list, SpamRules};
+use crate::template::Template;
+use failure::Error;
+use glob::Pattern;
+use irc::client::prelude::Config;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+// bot configuration keys, read from the [options] table of the irc config file
+const OPT_CACHE_TIME: &str = "cache_time";
+const OPT_MESSAGE_DELAY: &str = "message_delay";
+const OPT_DEFAULT_LIMIT: &str = "default_limit";
+const OPT_REPLY_TYPE: &str = "reply_type";
+const OPT_COMMAND_PREFIX: &str = "command_prefix";
+const OPT_QUIT_MESSAGE: &str = "quit_message";
+const OPT_NETWORK: &str = "network";
+const OPT_ALT_SERVERS: &str = "alt_servers";
+const OPT_RECONNECT_DELAY: &str = "reconnect_delay";
+const OPT_RECONNECT_MAX_DELAY: &str = "reconnect_max_delay";
+const OPT_RECONNECT_ATTEMPTS: &str = "reconnect_attempts";
+const OPT_RATE_LIMIT_COMMANDS: &str = "rate_limit_commands";
+const OPT_RATE_LIMIT_PERIOD: &str = "rate_limit_period";
+const OPT_RATE_LIMIT_PER_HOST: &str = "rate_limit_per_host";
+const OPT_ABUSE_STRIKES: &str = "abuse_strikes";
+const OPT_ABUSE_IGNORE_TIME: &str = "abuse_ignore_time";
+const OPT_REFRESH_COOLDOWN: &str = "refresh_cooldown";
+const OPT_IGNORE_FILE: &str = "ignore_file";
+const OPT_OPTOUT_FILE: &str = "optout_file";
+const OPT_DIRECTORY_FILE: &str = "directory_file";
+const OPT_FEATURED_FILE: &str = "featured_file";
+const OPT_HIDDEN_CHANNELS: &str = "hidden_channels";
+const OPT_HIDDEN_TOPICS: &str = "hidden_topics";
+const OPT_MIN_VISIBLE_USERS: &str = "min_visible_users";
+const OPT_SPAM_SHORTENERS: &str = "spam_shorteners";
+const OPT_SPAM_PHRASES: &str = "spam_phrases";
+const OPT_SPAM_MAX_REPEAT: &str = "spam_max_repeat";
+const OPT_SQUAT_MIN_USERS: &str = "squat_min_users";
+const OPT_SQUAT_MAX_DISTANCE: &str = "squat_max_distance";
+const OPT_AUDIT_LOG: &str = "audit_log";
+const OPT_AUDIT_LOG_MAX_SIZE: &str = "audit_log_max_size";
+const OPT_AUDIT_LOG_FILES: &str = "audit_log_files";
+const OPT_SEARCH_STATS_FILE: &str = "search_stats_file";
+const OPT_SEARCH_STATS_DAYS: &str = "search_stats_days";
+const OPT_PUBLIC_POPULAR: &str = "public_popular";
+const OPT_LANG: &str = "lang";
+const OPT_DEFAULT_SORT: &str = "default_sort";
+const OPT_COLUMNS: &str = "columns";
+const OPT_COLORS: &str = "colors";
+const OPT_TEMPLATE: &str = "template";
+const OPT_TRUNCATE_TOPICS: &str = "truncate_topics";
+const OPT_HIGHLIGHT: &str = "highlight";
+const OPT_PREFS_FILE: &str = "prefs_file";
+const OPT_SASL: &str = "sasl";
+const OPT_SASL_USERNAME: &str = "sasl_username";
+const OPT_SASL_PASSWORD: &str = "sasl_password";
+/// options used when connecting, a change restarts the connection
+pub const CONNECTION_OPTIONS: [&str; 3] = [OPT_SASL, OPT_SASL_USERNAME, OPT_SASL_PASSWORD];
+// default values
+pub const LIST_CACHE_TIME_SECS: u64 = 300; /* server list is cached for 5 min */
+const MESSAGE_DELAY_MS: u64 = 1000;
+const QUIT_MESSAGE: &str = "alis-bot-rs shutting down";
+const RECONNECT_DELAY_SECS: u64 = 5;
+const RECONNECT_MAX_DELAY_SECS: u64 = 600;
+const RATE_LIMIT_COMMANDS: usize = 5;
+const RATE_LIMIT_PERIOD_SECS: u64 = 60;
+const ABUSE_STRIKES: usize = 3;
+const ABUSE_IGNORE_TIME_SECS: u64 = 900;
+const REFRESH_COOLDOWN_SECS: u64 = 60;
+const AUDIT_LOG_MAX_SIZE_KB: u64 = 10240;
+const AUDIT_LOG_FILES: usize = 5;
+const SEARCH_STATS_DAYS: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyType {
+    Privmsg,
+    Notice,
+}
+
+impl FromStr for ReplyType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ReplyType, Error> {
+        match s.to_lowercase().as_str() {
+            "privmsg" => Ok(ReplyType::Privmsg),
+            "notice" => Ok(ReplyType::Notice),
+            _ => Err(format_err!(
+                "Unknown reply type '{}', expected 'privmsg' or 'notice'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ReplyType {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplyType::Privmsg => write!(formatter, "privmsg"),
+            ReplyType::Notice => write!(formatter, "notice"),
+        }
+    }
+}
+
+/// Order of `list` results, the server order when not set.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    /// most users first
+    Users,
+    Name,
+}
+
+impl FromStr for Sort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Sort, Error> {
+        match s.to_lowercase().as_str() {
+            "users" => Ok(Sort::Users),
+            "name" => Ok(Sort::Name),
+            _ => Err(localized!("value.invalid", s, "users, name")),
+        }
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sort::Users => write!(formatter, "users"),
+            Sort::Name => write!(formatter, "name"),
+        }
+    }
+}
+
+/// Channel detail shown in `list` results.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Name,
+    Users,
+    Topic,
+}
+
+pub const ALL_COLUMNS: [Column; 3] = [Column::Name, Column::Users, Column::Topic];
+
+impl FromStr for Column {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Column, Error> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(Column::Name),
+            "users" => Ok(Column::Users),
+            "topic" => Ok(Column::Topic),
+            _ => Err(localized!("value.invalid", s, "name, users, topic")),
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Column::Name => write!(formatter, "name"),
+            Column::Users => write!(formatter, "users"),
+            Column::Topic => write!(formatter, "topic"),
+        }
+    }
+}
+
+/// SASL mechanism logging the bot in before its registration ends.
+#[derive(Clone, PartialEq)]
+pub enum Sasl {
+    /// services account and password
+    Plain { username: String, password: String },
+    /// TLS client certificate given by `client_cert_path`
+    External,
+}
+
+/* keeps the password out of logs */
+impl fmt::Debug for Sasl {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sasl::Plain { username, .. } => write!(formatter, "Plain({})", username),
+            Sasl::External => write!(formatter, "External"),
+        }
+    }
+}
+
+impl Sasl {
+    pub fn mechanism(&self) -> &'static str {
+        match self {
+            Sasl::Plain { .. } => "PLAIN",
+            Sasl::External => "EXTERNAL",
+        }
+    }
+    /// Reads the `sasl` options, the account and password defaulting to the
+    /// nickname and `nick_password`.
+    fn from_config(config: &Config) -> Result<Option<Sasl>, Error> {
+        let mechanism = match config.get_option(OPT_SASL) {
+            Some(mechanism) if !mechanism.trim().is_empty() => mechanism.trim().to_lowercase(),
+            _ => return Ok(None),
+        };
+        match mechanism.as_str() {
+            "plain" => {
+                let username = match config.get_option(OPT_SASL_USERNAME) {
+                    Some(username) => username.to_string(),
+                    None => config.nickname()?.to_string(),
+                };
+                let password = match config.get_option(OPT_SASL_PASSWORD) {
+                    Some(password) => password.to_string(),
+                    None if !config.nick_password().is_empty() => {
+                        config.nick_password().to_string()
+                    }
+                    None => {
+                        return Err(format_err!(
+                            "Option {} is required for SASL PLAIN",
+                            OPT_SASL_PASSWORD
+                        ))
+                    }
+                };
+                Ok(Some(Sasl::Plain { username, password }))
+            }
+            "external" => match config.client_cert_path() {
+                Some(_) => Ok(Some(Sasl::External)),
+                None => Err(format_err!(
+                    "SASL EXTERNAL requires a TLS client certificate, set client_cert_path"
+                )),
+            },
+            _ => Err(format_err!(
+                "Unknown SASL mechanism '{}', expected 'plain' or 'external'",
+                mechanism
+            )),
+        }
+    }
+}
+
+/// Reads a comma-separated list of columns, e.g. `name,users`.
+pub fn parse_columns(list: &str) -> Result<Vec<Column>, Error> {
+    let mut columns = Vec::new();
+    for column in list.split(',').filter(|c| !c.trim().is_empty()) {
+        let column = column.trim().parse::<Column>()?;
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    if columns.is_empty() {
+        return Err(localized!("value.invalid", list, "name, users, topic"));
+    }
+    Ok(columns)
+}
+
+/// Alis specific settings, one set per network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// how long the server channel list is cached
+    pub cache_time: Duration,
+    /// delay between two result lines, to avoid anti-flooding policies
+    pub message_delay: Duration,
+    /// maximum number of result lines sent when the request has no --limit
+    pub default_limit: Option<u32>,
+    pub reply_type: ReplyType,
+    /// prefix required for commands sent to a channel, optional in private
+    pub command_prefix: Option<String>,
+    /// sent to the server when the bot shuts down
+    pub quit_message: String,
+    /// network name used in logs, the server address when not set
+    pub network: Option<String>,
+    /// servers tried in turn when the connection is lost
+    pub alt_servers: Vec<Server>,
+    /// delay before the first reconnection attempt, doubled on each failure
+    pub reconnect_delay: Duration,
+    pub reconnect_max_delay: Duration,
+    /// consecutive reconnection attempts before giving up, unlimited when not set
+    pub reconnect_attempts: Option<u32>,
+    pub rate_limits: RateLimits,
+    /// minimum delay between two channel list updates forced by users
+    pub refresh_cooldown: Duration,
+    /// ignored masks, kept across restarts when set
+    pub ignore_file: Option<PathBuf>,
+    /// channels delisted by their operators, kept across restarts when set
+    pub optout_file: Option<PathBuf>,
+    /// channel descriptions and tags registered by their operators
+    pub directory_file: Option<PathBuf>,
+    /// channels promoted by the owners
+    pub featured_file: Option<PathBuf>,
+    /// channels never returned, by name or by topic
+    pub hidden_channels: Vec<Pattern>,
+    pub hidden_topics: Vec<Pattern>,
+    /// channels with fewer users are only shown to owners
+    pub min_visible_users: Option<u32>,
+    /// heuristics of the owner `suspicious` report
+    pub spam_rules: SpamRules,
+    /// JSON-lines log of handled commands
+    pub audit_log: Option<PathBuf>,
+    /// size in bytes above which the audit log is rotated
+    pub audit_log_max_size: u64,
+    /// rotated audit logs kept
+    pub audit_log_files: usize,
+    /// search counters, kept across restarts when set
+    pub search_stats_file: Option<PathBuf>,
+    /// days of search counters kept
+    pub search_stats_days: u32,
+    /// allows everyone to see popular searches
+    pub public_popular: bool,
+    /// language of the replies to users who did not choose one
+    pub lang: Lang,
+    /// order of list results without --sort
+    pub default_sort: Option<Sort>,
+    /// channel details shown in list results
+    pub columns: Vec<Column>,
+    /// keeps bold, colors and other formatting codes in replies
+    pub colors: bool,
+    /// format of the result lines
+    pub template: Template,
+    /// shortens long result lines by truncating the topic instead of splitting them
+    pub truncate_topics: bool,
+    /// puts the parts of names and topics matched by patterns in bold
+    pub highlight: bool,
+    /// user preferences, kept across restarts when set
+    pub prefs_file: Option<PathBuf>,
+    /// SASL login on connection, instead of identifying to NickServ
+    pub sasl: Option<Sasl>,
+}
+
+/// Alternative server address, `host` or `host:port`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Server {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.port {
+            Some(port) => write!(formatter, "{}:{}", self.host, port),
+            None => write!(formatter, "{}", self.host),
+        }
+    }
+}
+
+impl FromStr for Server {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Server, Error> {
+        let s = s.trim();
+        let (host, port) = match s.rfind(':') {
+            Some(i) => match s[i + 1..].parse::<u16>() {
+                Ok(port) => (&s[..i], Some(port)),
+                Err(_) => return Err(format_err!("Invalid port in server address '{}'", s)),
+            },
+            None => (s, None),
+        };
+        if host.is_empty() {
+            return Err(format_err!("Invalid server address '{}'", s));
+        }
+        Ok(Server {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            cache_time: Duration::from_secs(LIST_CACHE_TIME_SECS),
+            message_delay: Duration::from_millis(MESSAGE_DELAY_MS),
+            default_limit: None,
+            reply_type: ReplyType::Privmsg,
+            command_prefix: None,
+            quit_message: QUIT_MESSAGE.to_string(),
+            network: None,
+            alt_servers: Vec::new(),
+            reconnect_delay: Duration::from_secs(RECONNECT_DELAY_SECS),
+            reconnect_max_delay: Duration::from_secs(RECONNECT_MAX_DELAY_SECS),
+            reconnect_attempts: None,
+            rate_limits: RateLimits {
+                commands: RATE_LIMIT_COMMANDS,
+                period: Duration::from_secs(RATE_LIMIT_PERIOD_SECS),
+                strikes: ABUSE_STRIKES,
+                ignore_time: Duration::from_secs(ABUSE_IGNORE_TIME_SECS),
+                per_host: false,
+            },
+            refresh_cooldown: Duration::from_secs(REFRESH_COOLDOWN_SECS),
+            ignore_file: None,
+            optout_file: None,
+            directory_file: None,
+            featured_file: None,
+            hidden_channels: Vec::new(),
+            hidden_topics: Vec::new(),
+            min_visible_users: None,
+            spam_rules: SpamRules::default(),
+            audit_log: None,
+            audit_log_max_size: AUDIT_LOG_MAX_SIZE_KB * 1024,
+            audit_log_files: AUDIT_LOG_FILES,
+            search_stats_file: None,
+            search_stats_days: SEARCH_STATS_DAYS,
+            public_popular: false,
+            lang: Lang::En,
+            default_sort: None,
+            columns: ALL_COLUMNS.to_vec(),
+            colors: true,
+            template: Template::default(),
+            truncate_topics: false,
+            highlight: true,
+            prefs_file: None,
+            sasl: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Result<Settings, Error> {
+        let default = Settings::default();
+        let cache_time = match parse_option::<u64>(config, OPT_CACHE_TIME)? {
+            Some(secs) => Duration::from_secs(secs),
+            None => default.cache_time,
+        };
+        let message_delay = match parse_option::<u64>(config, OPT_MESSAGE_DELAY)? {
+            Some(ms) => Duration::from_millis(ms),
+            None => default.message_delay,
+        };
+        let default_limit = parse_option::<u32>(config, OPT_DEFAULT_LIMIT)?;
+        let reply_type =
+            parse_option::<ReplyType>(config, OPT_REPLY_TYPE)?.unwrap_or(default.reply_type);
+        let command_prefix = config
+            .get_option(OPT_COMMAND_PREFIX)
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_string());
+        let quit_message = config
+            .get_option(OPT_QUIT_MESSAGE)
+            .map(|m| m.to_string())
+            .unwrap_or(default.quit_message);
+        let network = config
+            .get_option(OPT_NETWORK)
+            .map(|n| n.to_string())
+            .or_else(|| config.server.clone());
+        let alt_servers = match config.get_option(OPT_ALT_SERVERS) {
+            Some(list) => list
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.parse::<Server>())
+                .collect::<Result<Vec<Server>, Error>>()?,
+            None => default.alt_servers,
+        };
+        let reconnect_delay = match parse_option::<u64>(config, OPT_RECONNECT_DELAY)? {
+            /* no delay would reconnect in a loop */
+            Some(0) => {
+                return Err(format_err!(
+                    "Invalid value for option {}: must be at least 1",
+                    OPT_RECONNECT_DELAY
+                ))
+            }
+            Some(secs) => Duration::from_secs(secs),
+            None => default.reconnect_delay,
+        };
+        let reconnect_max_delay = match parse_option::<u64>(config, OPT_RECONNECT_MAX_DELAY)? {
+            Some(secs) => Duration::from_secs(secs),
+            None => default.reconnect_max_delay,
+        };
+        let reconnect_attempts = parse_option::<u32>(config, OPT_RECONNECT_ATTEMPTS)?;
+        let rate_limits = RateLimits {
+            commands: parse_option::<usize>(config, OPT_RATE_LIMIT_COMMANDS)?
+                .unwrap_or(default.rate_limits.commands),
+            period: match parse_option::<u64>(config, OPT_RATE_LIMIT_PERIOD)? {
+                Some(secs) => Duration::from_secs(secs),
+                None => default.rate_limits.period,
+            },
+            strikes: parse_option::<usize>(config, OPT_ABUSE_STRIKES)?
+                .unwrap_or(default.rate_limits.strikes),
+            ignore_time: match parse_option::<u64>(config, OPT_ABUSE_IGNORE_TIME)? {
+                Some(secs) => Duration::from_secs(secs),
+                None => default.rate_limits.ignore_time,
+            },
+            per_host: parse_option::<bool>(config, OPT_RATE_LIMIT_PER_HOST)?
+                .unwrap_or(default.rate_limits.per_host),
+        };
+        let refresh_cooldown = match parse_option::<u64>(config, OPT_REFRESH_COOLDOWN)? {
+            Some(secs) => Duration::from_secs(secs),
+            None => default.refresh_cooldown,
+        };
+        let ignore_file = config.get_option(OPT_IGNORE_FILE).map(PathBuf::from);
+        let optout_file = config.get_option(OPT_OPTOUT_FILE).map(PathBuf::from);
+        let directory_file = config.get_option(OPT_DIRECTORY_FILE).map(PathBuf::from);
+        let featured_file = config.get_option(OPT_FEATURED_FILE).map(PathBuf::from);
+        let hidden_channels = parse_patterns(config, OPT_HIDDEN_CHANNELS)?;
+        let hidden_topics = parse_patterns(config, OPT_HIDDEN_TOPICS)?;
+        let min_visible_users = parse_option::<u32>(config, OPT_MIN_VISIBLE_USERS)?;
+        let spam_rules = SpamRules {
+            shorteners: config
+                .get_option(OPT_SPAM_SHORTENERS)
+                .map(split_list)
+                .unwrap_or(default.spam_rules.shorteners),
+            phrases: config
+                .get_option(OPT_SPAM_PHRASES)
+                .map(split_list)
+                .unwrap_or(default.spam_rules.phrases),
+            max_repeat: parse_option::<usize>(config, OPT_SPAM_MAX_REPEAT)?
+                .unwrap_or(default.spam_rules.max_repeat),
+            established_users: parse_option::<u32>(config, OPT_SQUAT_MIN_USERS)?
+                .unwrap_or(default.spam_rules.established_users),
+            max_distance: parse_option::<usize>(config, OPT_SQUAT_MAX_DISTANCE)?
+                .unwrap_or(default.spam_rules.max_distance),
+        };
+        let audit_log = config.get_option(OPT_AUDIT_LOG).map(PathBuf::from);
+        let audit_log_max_size = match parse_option::<u64>(config, OPT_AUDIT_LOG_MAX_SIZE)? {
+            Some(kb) => kb.checked_mul(1024).ok_or_else(|| {
+                format_err!(
+                    "Invalid value for option {}: {} is too large",
+                    OPT_AUDIT_LOG_MAX_SIZE,
+                    kb
+                )
+            })?,
+            None => default.audit_log_max_size,
+        };
+        let audit_log_files =
+            parse_option::<usize>(config, OPT_AUDIT_LOG_FILES)?.unwrap_or(default.audit_log_files);
+        let search_stats_file = config.get_option(OPT_SEARCH_STATS_FILE).map(PathBuf::from);
+        let search_stats_days = parse_option::<u32>(config, OPT_SEARCH_STATS_DAYS)?
+            .unwrap_or(default.search_stats_days);
+        let public_popular =
+            parse_option::<bool>(config, OPT_PUBLIC_POPULAR)?.unwrap_or(default.public_popular);
+        let lang = parse_option::<Lang>(config, OPT_LANG)?.unwrap_or(default.lang);
+        let prefs_file = config.get_option(OPT_PREFS_FILE).map(PathBuf::from);
+        let default_sort = parse_option::<Sort>(config, OPT_DEFAULT_SORT)?;
+        let columns = match config.get_option(OPT_COLUMNS) {
+            Some(list) => parse_columns(list)
+                .map_err(|e| format_err!("Invalid value for option {}: {}", OPT_COLUMNS, e))?,
+            None => default.columns,
+        };
+        let colors = parse_option::<bool>(config, OPT_COLORS)?.unwrap_or(default.colors);
+        let template = parse_option::<Template>(config, OPT_TEMPLATE)?.unwrap_or(default.template);
+        let truncate_topics =
+            parse_option::<bool>(config, OPT_TRUNCATE_TOPICS)?.unwrap_or(default.truncate_topics);
+        let highlight = parse_option::<bool>(config, OPT_HIGHLIGHT)?.unwrap_or(default.highlight);
+        let sasl = Sasl::from_config(config)?;
+
+        Ok(Settings {
+            cache_time,
+            message_delay,
+            default_limit,
+            reply_type,
+            command_prefix,
+            quit_message,
+            network,
+            alt_servers,
+            reconnect_delay,
+            reconnect_max_delay,
+            reconnect_attempts,
+            rate_limits,
+            refresh_cooldown,
+            ignore_file,
+            optout_file,
+            directory_file,
+            featured_file,
+            hidden_channels,
+            hidden_topics,
+            min_visible_users,
+            spam_rules,
+            audit_log,
+            audit_log_max_size,
+            audit_log_files,
+            search_stats_file,
+            search_stats_days,
+            public_popular,
+            lang,
+            prefs_file,
+            default_sort,
+            columns,
+            colors,
+            template,
+            truncate_topics,
+            highlight,
+            sasl,
+        })
+    }
+}
+
+fn parse_option<T>(config: &Config, key: &str) -> Result<Option<T>, Error>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match config.get_option(key) {
+        Some(value) => match value.trim().parse::<T>() {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(format_err!("Invalid value for option {}: {}", key, e)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Reads a comma-separated list of glob patterns.
+fn parse_patterns(config: &Config, key: &str) -> Result<Vec<Pattern>, Error> {
+    match config.get_option(key) {
+        Some(list) => list
+            .split(',')
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                Pattern::new(p)
+                    .map_err(|e| format_err!("Invalid pattern '{}' for option {}: {}", p, key, e))
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config_with(options: &[(&str, &str)]) -> Config {
+        let mut map = HashMap::new();
+        for (k, v) in options {
+            map.insert(k.to_string(), v.to_string());
+        }
+        Config {
+            options: map,
+            ..Config::default()
+        }
+    }
+    #[test]
+    fn default_settings() {
+        let settings = Settings::from_config(&Config::default()).unwrap();
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.cache_time, Duration::from_secs(300));
+    }
+    #[test]
+    fn settings_from_options() {
+        let config = config_with(&[
+            ("cache_time", "60"),
+            ("message_delay", "500"),
+            ("default_limit", "20"),
+            ("reply_type", "NOTICE"),
+            ("command_prefix", "!"),
+            ("quit_message", "bye"),
+            ("rate_limit_commands", "3"),
+            ("refresh_cooldown", "120"),
+            ("lang", "FR"),
+            ("default_sort", "users"),
+            ("columns", "name, topic"),
+            ("colors", "false"),
+            ("template", "{name} - {topic:80}"),
+            ("truncate_topics", "true"),
+            ("highlight", "false"),
+        ]);
+        let settings = Settings::from_config(&config).unwrap();
+        assert_eq!(settings.cache_time, Duration::from_secs(60));
+        assert_eq!(settings.message_delay, Duration::from_millis(500));
+        assert_eq!(settings.default_limit, Some(20));
+        assert_eq!(settings.reply_type, ReplyType::Notice);
+        assert_eq!(settings.command_prefix, Some("!".to_string()));
+        assert_eq!(settings.quit_message, "bye");
+        assert_eq!(settings.rate_limits.commands, 3);
+        assert_eq!(settings.rate_limits.period, Duration::from_secs(60));
+        assert_eq!(settings.refresh_cooldown, Duration::from_secs(120));
+        assert_eq!(settings.lang, Lang::Fr);
+        assert_eq!(settings.default_sort, Some(Sort::Users));
+        assert_eq!(settings.columns, vec![Column::Name, Column::Topic]);
+        assert!(!settings.colors);
+        assert_eq!(settings.template.to_string(), "{name} - {topic:80}");
+        assert!(settings.truncate_topics);
+        assert!(!settings.highlight);
+    }
+    #[test]
+    fn hidden_channels() {
+        let config = config_with(&[
+            ("hidden_channels", "#*-ops, ##staff*,"),
+            ("hidden_topics", "*private*"),
+            ("min_visible_users", "3"),
+        ]);
+        let settings = Settings::from_config(&config).unwrap();
+        let patterns: Vec<&str> = settings
+            .hidden_channels
+            .iter()
+            .map(|p| p.as_str())
+            .collect();
+        assert_eq!(patterns, vec!["#*-ops", "##staff*"]);
+        assert_eq!(settings.hidden_topics.len(), 1);
+        assert_eq!(settings.min_visible_users, Some(3));
+    }
+    #[test]
+    fn spam_rules() {
+        let config = config_with(&[
+            ("spam_phrases", "Buy Now, ,cheap"),
+            ("squat_max_distance", "2"),
+        ]);
+        let settings = Settings::from_config(&config).unwrap();
+        assert_eq!(settings.spam_rules.phrases, vec!["buy now", "cheap"]);
+        assert_eq!(settings.spam_rules.max_distance, 2);
+        assert_eq!(
+            settings.spam_rules.shorteners,
+            SpamRules::default().shorteners
+        );
+    }
+    #[test]
+    fn invalid_options() {
+        let config = config_with(&[("cache_time", "five")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("reply_type", "ctcp")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("alt_servers", "irc.example.org:port")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("hidden_channels", "#ops, #[staff")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("lang", "klingon")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("columns", "name,modes")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("columns", ",")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("template", "{name} {modes}")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("reconnect_delay", "0")]);
+        assert!(Settings::from_config(&config).is_err());
+        let config = config_with(&[("audit_log_max_size", "18446744073709551615")]);
+        assert!(Settings::from_config(&config).is_err());
+    }
+    #[test]
+    fn alternative_servers() {
+        let config = config_with(&[("alt_servers", "irc1.example.org, irc2.example.org:6697")]);
+        let settings = Settings::from_config(&config).unwrap();
+        let expected = vec![
+            Server {
+                host: "irc1.example.org".to_string(),
+                port: None,
+            },
+            Server {
+                host: "irc2.example.org".to_string(),
+                port: Some(6697),
+            },
+        ];
+        assert_eq!(settings.alt_servers, expected);
+    }
+    #[test]
+    fn sasl() {
+        let config = Config {
+            nickname: Some("alis".to_string()),
+            nick_password: Some("secret".to_string()),
+            ..config_with(&[("sasl", "PLAIN")])
+        };
+        let settings = Settings::from_config(&config).unwrap();
+        let expected = Sasl::Plain {
+            username: "alis".to_string(),
+            password: "secret".to_string(),
+        };
+        assert_eq!(settings.sasl, Some(expected));
+        assert_eq!(format!("{:?}", settings.sasl), "Some(Plain(alis))");
+        let config = config_with(&[
+            ("sasl", "plain"),
+            ("sasl_username", "account"),
+            ("sasl_password", "pass"),
+        ]);
+        let settings = Settings::from_config(&config).unwrap();
+        let expected = Sasl::Plain {
+            username: "account".to_string(),
+            password: "pass".to_string(),
+        };
+        assert_eq!(settings.sasl, Some(expected));
+        let config = Config {
+            client_cert_path: Some("alis.p12".to_string()),
+            ..config_with(&[("sasl", "external")])
+        };
+        let settings = Settings::from_config(&config).unwrap();
+        assert_eq!(settings.sasl, Some(Sasl::External));
+        /* missing password or certificate, unknown mechanism */
+        for options in &[
+            [("sasl", "plain"), ("sasl_username", "account")],
+            [("sasl", "external"), ("network", "x")],
+            [("sasl", "scram-sha-256"), ("network", "x")],
+        ] {
+            assert!(Settings::from_config(&config_with(options)).is_err());
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use crate::settings::ALL_COLUMNS;
+use crate::{listing_guard, send_replies, send_reply, Channel, Context, Settings, User};
+use crate::{BotCommand, Lang, Outcome, Permission};
+use log::info;
+use std::cmp::Reverse;
+
+pub const SUSPICIOUS_COMMAND: &str = "suspicious";
+
+const DEFAULT_REPORT_LIMIT: usize = 20;
+/* channels compared with the others for squatting, largest first */
+const ESTABLISHED_CHANNELS: usize = 100;
+const SHORTENERS: &str =
+    "bit.ly, tinyurl.com, goo.gl, t.co, is.gd, ow.ly, cutt.ly, rb.gy, shorturl.at";
+const PHRASES: &str = "free money, crypto giveaway, double your, click here, join now, earn $";
+
+/// Heuristics used by the `suspicious` report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpamRules {
+    /// URL shortener domains flagged in topics
+    pub shorteners: Vec<String>,
+    /// lowercase phrases flagged in topics
+    pub phrases: Vec<String>,
+    /// longest run of a repeated character allowed in topics
+    pub max_repeat: usize,
+    /// channels with at least this many users are checked for squatters
+    pub established_users: u32,
+    /// names at this edit distance or closer to an established channel are flagged
+    pub max_distance: usize,
+}
+
+impl Default for SpamRules {
+    fn default() -> SpamRules {
+        SpamRules {
+            shorteners: split_list(SHORTENERS),
+            phrases: split_list(PHRASES),
+            max_repeat: 8,
+            established_users: 100,
+            max_distance: 1,
+        }
+    }
+}
+
+/// Splits a comma-separated option value into lowercase items.
+pub fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Maps characters commonly used to imitate latin letters to them.
+fn unconfuse(c: char) -> Option<char> {
+    let c = match c {
+        /* fullwidth forms */
+        '\u{ff01}'..='\u{ff5e}' => std::char::from_u32(c as u32 - 0xfee0)?,
+        _ => c,
+    };
+    let c = c.to_lowercase().next()?;
+    Some(match c {
+        /* zero width characters */
+        '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => return None,
+        'а' | 'α' => 'a',
+        'в' | 'β' => 'b',
+        'с' | 'ϲ' => 'c',
+        'е' | 'ε' | '3' => 'e',
+        'һ' => 'h',
+        /* i, l, 1 and | are told apart by their font only */
+        'i' | 'l' | 'і' | 'ι' | 'ı' | '1' | '|' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'м' => 'm',
+        'о' | 'ο' | '0' => 'o',
+        'р' | 'ρ' => 'p',
+        'ѕ' | '5' => 's',
+        'т' | 'τ' => 't',
+        'υ' | 'ս' => 'u',
+        'ν' => 'v',
+        'ш' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        c => c,
+    })
+}
+
+/// Confusable skeleton of a channel name: names looking alike share it.
+fn skeleton(name: &str) -> String {
+    name.chars()
+        .filter_map(unconfuse)
+        .collect::<String>()
+        .replace("rn", "m")
+        .replace("vv", "w")
+}
+
+/// Levenshtein distance, giving up once it exceeds `max`.
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().max(b.len()) - a.len().min(b.len()) > max {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().all(|d| *d > max) {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|d| *d <= max)
+}
+
+fn longest_run(s: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut last = None;
+    for c in s.chars() {
+        if Some(c) == last && !c.is_whitespace() {
+            run += 1;
+        } else {
+            run = 1;
+            last = Some(c);
+        }
+        longest = longest.max(run);
+    }
+    longest
+}
+
+/// Why the report flags a channel.
+#[derive(Debug, Clone, PartialEq)]
+enum Reason {
+    Shortener(String),
+    Phrase(String),
+    Repeated(usize),
+    LooksLike(String),
+    CloseTo(String),
+}
+
+impl Reason {
+    fn text(&self, lang: Lang) -> String {
+        match self {
+            Reason::Shortener(domain) => tr!(lang, "suspicious.shortener", domain),
+            Reason::Phrase(phrase) => tr!(lang, "suspicious.phrase", phrase),
+            Reason::Repeated(run) => tr!(lang, "suspicious.repeated", run),
+            Reason::LooksLike(name) => tr!(lang, "suspicious.looks_like", name),
+            Reason::CloseTo(name) => tr!(lang, "suspicious.close_to", name),
+        }
+    }
+}
+
+/// Reasons why a topic looks like spam.
+fn topic_flags(topic: &str, rules: &SpamRules) -> Vec<Reason> {
+    let topic = topic.to_lowercase();
+    let mut flags = Vec::new();
+    if let Some(domain) = rules
+        .shorteners
+        .iter()
+        .find(|d| topic.contains(&format!("{}/", d)))
+    {
+        flags.push(Reason::Shortener(domain.clone()));
+    }
+    if let Some(phrase) = rules.phrases.iter().find(|p| topic.contains(p.as_str())) {
+        flags.push(Reason::Phrase(phrase.clone()));
+    }
+    let run = longest_run(&topic);
+    if run > rules.max_repeat {
+        flags.push(Reason::Repeated(run));
+    }
+    flags
+}
+
+/// A channel flagged by the report, with its reasons.
+struct Flagged<'a> {
+    channel: &'a Channel,
+    reasons: Vec<Reason>,
+}
+
+/// Checks every channel topic, and names against the largest channels.
+fn find_suspicious<'a>(channels: &'a [Channel], rules: &SpamRules) -> Vec<Flagged<'a>> {
+    let mut established: Vec<&Channel> = channels
+        .iter()
+        .filter(|c| c.users >= rules.established_users)
+        .collect();
+    established.sort_by_key(|c| Reverse(c.users));
+    established.truncate(ESTABLISHED_CHANNELS);
+    let established: Vec<(&Channel, String, String)> = established
+        .into_iter()
+        .map(|c| (c, c.name.to_lowercase(), skeleton(&c.name)))
+        .collect();
+
+    let mut flagged: Vec<Flagged> = channels
+        .iter()
+        .filter_map(|channel| {
+            let mut reasons = topic_flags(&channel.topic, rules);
+            let name = channel.name.to_lowercase();
+            let name_skeleton = skeleton(&channel.name);
+            for (big, big_name, big_skeleton) in established.iter() {
+                if big.users <= channel.users || *big_name == name {
+                    continue;
+                }
+                if *big_skeleton == name_skeleton {
+                    reasons.push(Reason::LooksLike(big.name.clone()));
+                } else if edit_distance(&name, big_name, rules.max_distance).is_some() {
+                    reasons.push(Reason::CloseTo(big.name.clone()));
+                }
+            }
+            if reasons.is_empty() {
+                None
+            } else {
+                Some(Flagged { channel, reasons })
+            }
+        })
+        .collect();
+    flagged.sort_by_key(|f| Reverse(f.channel.users));
+    flagged
+}
+
+/// `suspicious [limit]`: owner report of the suspicious channels found in the
+/// cached list.
+pub struct SuspiciousCommand;
+
+impl BotCommand for SuspiciousCommand {
+    fn name(&self) -> &str {
+        SUSPICIOUS_COMMAND
+    }
+    fn syntax(&self) -> &str {
+        "suspicious [limit]"
+    }
+    fn summary(&self) -> &str {
+        "reports channels looking like spam or squatting"
+    }
+    fn permission(&self) -> Permission {
+        Permission::Owner
+    }
+    fn run(&self, ctx: &Context, settings: &Settings, user: &User, args: &[&str]) -> Outcome {
+        info!("Admin command from {}: {}", user.hostmask, args.join(" "));
+        suspicious_command(ctx, settings, user, args);
+        Outcome::default()
+    }
+}
+
+fn suspicious_command(ctx: &Context, settings: &Settings, user: &User, args: &[&str]) {
+    let lang = ctx.lang(settings, user);
+    let limit = match args.get(1).map(|l| l.parse::<usize>()) {
+        Some(Ok(limit)) => limit,
+        Some(Err(_)) => {
+            send_reply(ctx, settings, &user.nick, tr!(lang, "suspicious.usage"));
+            return;
+        }
+        None => DEFAULT_REPORT_LIMIT,
+    };
+    let lines: Vec<String> = {
+        let guard = listing_guard(ctx, settings, false);
+        let flagged = find_suspicious(&guard.1.channels, &settings.spam_rules);
+        let mut lines: Vec<String> = flagged
+            .iter()
+            .take(limit)
+            .map(|f| {
+                let line = f.channel.line(&settings.template, &ALL_COLUMNS);
+                let reasons: Vec<String> = f.reasons.iter().map(|r| r.text(lang)).collect();
+                format!("{} - {}", line, reasons.join(", "))
+            })
+            .collect();
+        lines.push(tr!(lang, "suspicious.total", flagged.len(), guard.1.len()));
+        lines
+    };
+    send_replies(ctx, settings, &user.nick, &lines);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(name: &str, users: u32, topic: &str) -> Channel {
+        Channel {
+            name: name.to_string(),
+            topic: topic.to_string(),
+            users,
+        }
+    }
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("#rust", "#rust", 1), Some(0));
+        assert_eq!(edit_distance("#rust", "#rusty", 1), Some(1));
+        assert_eq!(edit_distance("#rust", "#ruts", 2), Some(2));
+        assert_eq!(edit_distance("#rust", "#ruts", 1), None);
+        assert_eq!(edit_distance("#rust", "#python", 3), None);
+    }
+    #[test]
+    fn confusable_names() {
+        assert_eq!(skeleton("#Rust"), skeleton("#rսst"));
+        assert_eq!(skeleton("#libera"), skeleton("#1ibera"));
+        assert_eq!(skeleton("#modern"), skeleton("#rnodern"));
+        assert_eq!(skeleton("#ubuntu"), skeleton("#ｕｂｕｎｔｕ"));
+        assert_eq!(skeleton("#linux"), skeleton("#lin\u{200b}ux"));
+        assert_eq!(skeleton("#linux"), skeleton("#l\u{456}nux"));
+        assert_eq!(skeleton("#libera"), skeleton("#Iibera"));
+        assert_eq!(skeleton("#libera"), skeleton("#l\u{3b9}bera"));
+        assert_ne!(skeleton("#rust"), skeleton("#rest"));
+    }
+    #[test]
+    fn spam_topics() {
+        let rules = SpamRules::default();
+        assert!(topic_flags("Rust programming language", &rules).is_empty());
+        assert_eq!(
+            topic_flags("Free MONEY at https://bit.ly/xyz", &rules),
+            vec![
+                Reason::Shortener("bit.ly".to_string()),
+                Reason::Phrase("free money".to_string())
+            ]
+        );
+        assert_eq!(
+            topic_flags(&format!("join{}", "!".repeat(12)), &rules),
+            vec![Reason::Repeated(12)]
+        );
+        assert_eq!(
+            Reason::Repeated(12).text(Lang::En),
+            "12 repeated characters"
+        );
+        assert_eq!(
+            Reason::Phrase("earn $".to_string()).text(Lang::Fr),
+            "phrase de spam 'earn $'"
+        );
+    }
+    #[test]
+    fn report() {
+        let channels = vec![
+            channel("#rust", 500, "Rust"),
+            channel("#rust-offtopic", 50, "Off topic"),
+            channel("#rusts", 3, "Rust"),
+            channel("#rսst", 2, "Rust"),
+            channel("#spam", 1, "click here http://tinyurl.com/a"),
+            channel("#ruby", 120, "Ruby"),
+        ];
+        let flagged = find_suspicious(&channels, &SpamRules::default());
+        let names: Vec<&str> = flagged.iter().map(|f| f.channel.name.as_str()).collect();
+        assert_eq!(names, vec!["#rusts", "#rսst", "#spam"]);
+        assert_eq!(
+            flagged[0].reasons,
+            vec![Reason::CloseTo("#rust".to_string())]
+        );
+        assert_eq!(
+            flagged[1].reasons,
+            vec![Reason::LooksLike("#rust".to_string())]
+        );
+        assert_eq!(flagged[2].reasons.len(), 2);
+    }
+    #[test]
+    fn i_and_l_lookalikes() {
+        let channels = vec![
+            channel("#linux", 900, "Linux"),
+            channel("#libera", 400, "Libera.Chat"),
+            channel("#l\u{456}nux", 4, "Linux"),
+            channel("#Iibera", 3, "Libera.Chat"),
+        ];
+        let flagged = find_suspicious(&channels, &SpamRules::default());
+        let reasons: Vec<(&str, &[Reason])> = flagged
+            .iter()
+            .map(|f| (f.channel.name.as_str(), f.reasons.as_slice()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (
+                    "#l\u{456}nux",
+                    &[Reason::LooksLike("#linux".to_string())][..]
+                ),
+                ("#Iibera", &[Reason::LooksLike("#libera".to_string())][..]),
+            ]
+        );
+    }
+}
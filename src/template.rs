@@ -0,0 +1,252 @@
+use crate::formatting;
+use crate::settings::Column;
+use failure::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Result line used when the network does not configure one.
+pub const DEFAULT_TEMPLATE: &str = "{name} ({users}) {topic}";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Width {
+    Any,
+    /// truncated to at most this number of characters
+    Max(usize),
+    /// padded with spaces on the right
+    Left(usize),
+    /// padded with spaces on the left
+    Right(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Column, Width),
+}
+
+/// Format of a result line, e.g. `{bold}{name}{reset} ({users}) - {topic:80}`.
+///
+/// Fields are `{name}`, `{users}` and `{topic}`, optionally followed by a width:
+/// `{topic:80}` truncates to 80 characters, `{name:<25}` and `{users:>5}` pad
+/// with spaces. `{bold}`, `{italic}`, `{underline}`, `{color:FG[,BG]}` and
+/// `{reset}` insert IRC formatting codes, `{{` and `}}` literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Line with the fields of a channel.
+    pub fn render<F: Fn(Column) -> String>(&self, value: F) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => line.push_str(text),
+                Part::Field(column, width) => line.push_str(&apply(&value(*column), *width)),
+            }
+        }
+        line.trim_end().to_string()
+    }
+    /// A single field with the width the template gives it, if any.
+    pub fn field(&self, column: Column, value: &str) -> String {
+        let width = self.parts.iter().find_map(|part| match part {
+            Part::Field(c, width) if *c == column => Some(*width),
+            _ => None,
+        });
+        apply(value, width.unwrap_or(Width::Any))
+    }
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        DEFAULT_TEMPLATE.parse().unwrap()
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Template, Error> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = match rest.find('}') {
+                        Some(end) => end,
+                        None => return Err(format_err!("unclosed '{{' in '{}'", s)),
+                    };
+                    match parse_placeholder(&rest[..end])? {
+                        Part::Text(code) => text.push_str(&code),
+                        field => {
+                            if !text.is_empty() {
+                                parts.push(Part::Text(text.split_off(0)));
+                            }
+                            parts.push(field);
+                        }
+                    }
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format_err!("unexpected '}}' in '{}', use '}}}}'", s)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template {
+            source: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.source)
+    }
+}
+
+/// Reads the inside of `{...}`, a field or a formatting code.
+fn parse_placeholder(placeholder: &str) -> Result<Part, Error> {
+    let (name, spec) = match placeholder.find(':') {
+        Some(i) => (&placeholder[..i], Some(&placeholder[i + 1..])),
+        None => (placeholder, None),
+    };
+    let code = match (name, spec) {
+        ("bold", None) => "\x02".to_string(),
+        ("italic", None) => "\x1d".to_string(),
+        ("underline", None) => "\x1f".to_string(),
+        ("reset", None) => "\x0f".to_string(),
+        ("color", Some(colors)) => {
+            let valid = colors.split(',').count() <= 2
+                && colors.split(',').all(|color| {
+                    !color.is_empty()
+                        && color.len() <= 2
+                        && color.chars().all(|c| c.is_ascii_digit())
+                });
+            if !valid {
+                return Err(format_err!(
+                    "invalid color '{}', expected FG or FG,BG",
+                    colors
+                ));
+            }
+            /* two digits so that a topic starting with a digit is not read as a color */
+            let colors: Vec<String> = colors
+                .split(',')
+                .map(|color| format!("{:0>2}", color))
+                .collect();
+            format!("\x03{}", colors.join(","))
+        }
+        _ => {
+            let column = name
+                .parse::<Column>()
+                .map_err(|_| format_err!("unknown field '{{{}}}'", placeholder))?;
+            let width = match spec {
+                Some(spec) => parse_width(spec)
+                    .ok_or_else(|| format_err!("invalid width in '{{{}}}'", placeholder))?,
+                None => Width::Any,
+            };
+            return Ok(Part::Field(column, width));
+        }
+    };
+    Ok(Part::Text(code))
+}
+
+fn parse_width(spec: &str) -> Option<Width> {
+    let (align, number): (fn(usize) -> Width, &str) = if let Some(n) = spec.strip_prefix('<') {
+        (Width::Left, n)
+    } else if let Some(n) = spec.strip_prefix('>') {
+        (Width::Right, n)
+    } else {
+        (Width::Max, spec)
+    };
+    match number.parse::<usize>() {
+        Ok(n) if n > 0 => Some(align(n)),
+        _ => None,
+    }
+}
+
+/* widths count visible characters, the formatting codes of a value excluded */
+fn apply(value: &str, width: Width) -> String {
+    let padding = |n: usize| " ".repeat(n.saturating_sub(formatting::visible_len(value)));
+    match width {
+        Width::Any => value.to_string(),
+        Width::Max(n) => formatting::truncate_chars(value, n),
+        Width::Left(n) => format!("{}{}", value, padding(n)),
+        Width::Right(n) => format!("{}{}", padding(n), value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(column: Column) -> String {
+        match column {
+            Column::Name => "#rust".to_string(),
+            Column::Users => "120".to_string(),
+            Column::Topic => "Rust programming language".to_string(),
+        }
+    }
+    #[test]
+    fn render_templates() {
+        let render = |template: &str| template.parse::<Template>().unwrap().render(channel);
+        assert_eq!(
+            render(DEFAULT_TEMPLATE),
+            "#rust (120) Rust programming language"
+        );
+        assert_eq!(
+            render("{name:<8}{users:>5}: {topic:8}"),
+            "#rust     120: Rust pr…"
+        );
+        assert_eq!(
+            render("{bold}{name}{reset} {{{users}}} {color:4}{topic:4}"),
+            "\x02#rust\x0f {120} \x0304Rus…"
+        );
+        assert_eq!(render("{color:3,12}{name}"), "\x0303,12#rust");
+        assert_eq!(
+            render("{name} {topic:100}"),
+            "#rust Rust programming language"
+        );
+        let template = "{name:<8} {topic:4}".parse::<Template>().unwrap();
+        assert_eq!(template.field(Column::Topic, "Rust"), "Rust");
+        assert_eq!(template.field(Column::Name, "#foo"), "#foo    ");
+        assert_eq!(template.field(Column::Users, "3"), "3");
+        assert_eq!(template.to_string(), "{name:<8} {topic:4}");
+        assert_eq!(
+            template.field(Column::Name, "\x02#foo\x02"),
+            "\x02#foo\x02    "
+        );
+        assert_eq!(
+            template.field(Column::Topic, "\x02Rust\x02 lang"),
+            "\x02Rus\x02…"
+        );
+    }
+    #[test]
+    fn invalid_templates() {
+        for template in &[
+            "{name",
+            "{name}}",
+            "{modes}",
+            "{topic:0}",
+            "{topic:x}",
+            "{color:123}",
+            "{color:1,2,3}",
+            "{bold:1}",
+        ] {
+            assert!(template.parse::<Template>().is_err(), "{}", template);
+        }
+    }
+}
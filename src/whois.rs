@@ -0,0 +1,162 @@
+use irc::client::prelude::*;
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+// not known by irc-proto, received as a raw command
+const RPL_WHOISACCOUNT: &str = "330";
+const WHOIS_TIMEOUT: Duration = Duration::from_secs(10);
+/* membership prefixes of channel operators: founder, admin, operator */
+const OP_PREFIXES: &[char] = &['~', '&', '@'];
+
+/// What the server told about a user in its WHOIS reply.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WhoisInfo {
+    /// services account the user is logged in
+    pub account: Option<String>,
+    /// channels the user is in, with their membership prefixes (e.g. `@#chan`)
+    pub channels: Vec<String>,
+}
+
+impl WhoisInfo {
+    /// Whether the user is an operator of `channel`. Secret channels only appear
+    /// when the bot shares them with the user.
+    pub fn is_op(&self, channel: &str) -> bool {
+        self.channels.iter().any(|entry| match entry.find('#') {
+            Some(i) => entry[i..].eq_ignore_ascii_case(channel) && entry[..i].contains(OP_PREFIXES),
+            None => false,
+        })
+    }
+}
+
+/// WHOIS queries sent by the privmsg worker and answered from the IRC stream.
+pub struct Whois {
+    /* (reply complete, info) by lowercase nickname */
+    queries: Mutex<HashMap<String, (bool, WhoisInfo)>>,
+    cnd: Condvar,
+}
+
+impl Default for Whois {
+    fn default() -> Whois {
+        Whois::new()
+    }
+}
+
+impl Whois {
+    pub fn new() -> Whois {
+        Whois {
+            queries: Mutex::new(HashMap::new()),
+            cnd: Condvar::new(),
+        }
+    }
+    /// Sends a WHOIS for `nick` and waits for the server reply. Blocks the calling
+    /// thread, never call it from the IRC stream task.
+    pub fn query(&self, client: &Client, nick: &str) -> Option<WhoisInfo> {
+        let key = nick.to_lowercase();
+        let mut queries = self.queries.lock().unwrap();
+        queries.insert(key.clone(), (false, WhoisInfo::default()));
+        if client.send(Command::WHOIS(None, nick.to_string())).is_err() {
+            queries.remove(&key);
+            return None;
+        }
+        debug!("Waiting for WHOIS reply on {}...", nick);
+        let (mut queries, wait) = self
+            .cnd
+            .wait_timeout_while(queries, WHOIS_TIMEOUT, |q| match q.get(&key) {
+                Some((complete, _)) => !complete,
+                None => false,
+            })
+            .unwrap();
+        let reply = queries.remove(&key);
+        if wait.timed_out() {
+            debug!("No WHOIS reply on {}", nick);
+            return None;
+        }
+        reply.map(|(_, info)| info)
+    }
+    /// Collects the WHOIS replies received on the IRC stream.
+    pub fn handle(&self, message: &Message) {
+        match &message.command {
+            Command::Raw(code, args) if code == RPL_WHOISACCOUNT && args.len() >= 3 => {
+                self.update(&args[1], |info| info.account = Some(args[2].clone()));
+            }
+            Command::Response(Response::RPL_WHOISCHANNELS, args) if args.len() >= 3 => {
+                self.update(&args[1], |info| {
+                    info.channels
+                        .extend(args[2].split_whitespace().map(|c| c.to_string()))
+                });
+            }
+            Command::Response(Response::RPL_ENDOFWHOIS, args)
+            | Command::Response(Response::ERR_NOSUCHNICK, args)
+                if args.len() >= 2 =>
+            {
+                let mut queries = self.queries.lock().unwrap();
+                if let Some(query) = queries.get_mut(&args[1].to_lowercase()) {
+                    query.0 = true;
+                    self.cnd.notify_all();
+                }
+            }
+            _ => (),
+        }
+    }
+    fn update<F: FnOnce(&mut WhoisInfo)>(&self, nick: &str, f: F) {
+        let mut queries = self.queries.lock().unwrap();
+        if let Some((_, info)) = queries.get_mut(&nick.to_lowercase()) {
+            f(info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_whois_replies() {
+        let whois = Whois::new();
+        whois
+            .queries
+            .lock()
+            .unwrap()
+            .insert("nick".to_string(), (false, WhoisInfo::default()));
+        let replies = [
+            ":server 311 bot Nick user host * :real name\r\n",
+            ":server 319 bot Nick :@#ops #rust\r\n",
+            ":server 330 bot Nick account :is logged in as\r\n",
+            ":server 318 bot Nick :End of /WHOIS list.\r\n",
+        ];
+        for reply in replies.iter() {
+            whois.handle(&reply.parse::<Message>().unwrap());
+        }
+        let queries = whois.queries.lock().unwrap();
+        let (complete, info) = &queries["nick"];
+        assert!(complete);
+        assert_eq!(info.account, Some("account".to_string()));
+        assert_eq!(info.channels, vec!["@#ops", "#rust"]);
+    }
+    #[test]
+    fn channel_operators() {
+        let info = WhoisInfo {
+            account: None,
+            channels: vec!["@#ops", "+#voiced", "#rust", "@+#both", "~#founder"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        };
+        assert!(info.is_op("#ops"));
+        assert!(info.is_op("#OPS"));
+        assert!(info.is_op("#both"));
+        assert!(info.is_op("#founder"));
+        assert!(!info.is_op("#voiced"));
+        assert!(!info.is_op("#rust"));
+        assert!(!info.is_op("#other"));
+    }
+    #[test]
+    fn ignore_unknown_queries() {
+        let whois = Whois::new();
+        let reply = ":server 330 bot other account :is logged in as\r\n";
+        whois.handle(&reply.parse::<Message>().unwrap());
+        assert!(whois.queries.lock().unwrap().is_empty());
+    }
+}